@@ -0,0 +1,24 @@
+use prost::Message;
+use std::path::PathBuf;
+
+/// 编译 gRPC 的 proto 文件
+///
+/// 使用纯 Rust 实现的 protox 解析 proto 文件, 因此构建时不需要安装 protoc.
+/// 编码之后的文件描述符也会被写入 OUT_DIR, 供服务反射使用
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let proto = "proto/web_start.proto";
+    println!("cargo:rerun-if-changed={proto}");
+
+    let descriptors = protox::compile([proto], ["proto"])?;
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+    std::fs::write(
+        out_dir.join("web_start_descriptor.bin"),
+        descriptors.encode_to_vec(),
+    )?;
+
+    tonic_build::configure()
+        .build_client(false)
+        .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .compile_fds(descriptors)?;
+    Ok(())
+}
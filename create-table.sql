@@ -42,17 +42,85 @@ CREATE TABLE users(
     id          VARCHAR(32),
     name        VARCHAR(32) NOT NULL,
     password    VARCHAR(128) NOT NULL,
+    role        VARCHAR(16) NOT NULL DEFAULT 'teacher' CHECK (role IN('teacher','registrar','admin')),
     PRIMARY KEY (id)
 );
 
+CREATE TABLE term(
+    id                              CHAR(6)         NOT NULL UNIQUE,
+    name                            VARCHAR(20)     NOT NULL,
+    start_date                      date            NOT NULL,
+    end_date                        date            NOT NULL,
+    closed                          BOOLEAN         NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (id),
+    CHECK (start_date <= end_date)
+);
+
+-- 成绩的每一个版本都会保存在这里, 不引用 score 表, 以便成绩被删除后仍保留历史
+CREATE TABLE score_history(
+    id                              SERIAL          NOT NULL,
+    stu_id                          CHAR(6)         NOT NULL,
+    course_id                       CHAR(6)         NOT NULL,
+    old_score                       INT,
+    new_score                       INT,
+    reason                          VARCHAR(200),
+    status                          VARCHAR(16)     NOT NULL CHECK (status IN('direct','pending','approved','rejected','deleted')),
+    proposed_by                     VARCHAR(32)     NOT NULL,
+    proposed_at                     TIMESTAMP       NOT NULL DEFAULT current_timestamp,
+    reviewed_by                     VARCHAR(32),
+    reviewed_at                     TIMESTAMP,
+    review_comment                  VARCHAR(200),
+    PRIMARY KEY (id),
+    FOREIGN KEY (stu_id)            REFERENCES      student(id),
+    FOREIGN KEY (course_id)         REFERENCES      course(id)
+);
+
+CREATE INDEX score_history_score_idx ON score_history(stu_id, course_id);
+-- 每条成绩最多只有一条待审批的修改申请
+CREATE UNIQUE INDEX score_history_pending_idx ON score_history(stu_id, course_id) WHERE status = 'pending';
+
+-- 每一份打印出去的成绩单都会在这里留下快照, 以便通过验证码核验真伪
+CREATE TABLE transcript(
+    code                            CHAR(16)        NOT NULL,
+    stu_id                          CHAR(6)         NOT NULL,
+    issued_by                       VARCHAR(32)     NOT NULL,
+    issued_at                       TIMESTAMP       NOT NULL DEFAULT current_timestamp,
+    content                         JSONB           NOT NULL,
+    PRIMARY KEY (code),
+    FOREIGN KEY (stu_id)            REFERENCES      student(id)
+);
+
+-- 带有 Idempotency-Key 的 POST 请求及其响应, 重试时直接返回保存的响应, 过期的记录会被定期清理
+CREATE TABLE idempotency_key(
+    user_id                         VARCHAR(32)     NOT NULL,
+    key                             VARCHAR(255)    NOT NULL,
+    request_hash                    CHAR(64)        NOT NULL,
+    status                          INT,            -- 为空表示请求还在处理中
+    content_type                    VARCHAR(255),
+    body                            BYTEA,
+    created_at                      TIMESTAMP       NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (user_id, key)
+);
+CREATE INDEX idempotency_key_created_at_idx ON idempotency_key (created_at);
+
 CREATE VIEW student_score_course(stu_name, stu_id, score, course_name, course_id, record_date) AS
 SELECT s.name, s.id, sc.score, c.name, c.id, sc.record_date
 FROM student s, score sc, course c
 WHERE s.id = sc.stu_id AND c.id = sc.course_id;
 
+-- 全站搜索用 pg_trgm 的三元组索引加速相似度匹配和 ILIKE
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX student_name_trgm_idx ON student USING gin (name gin_trgm_ops);
+CREATE INDEX course_name_trgm_idx ON course USING gin (name gin_trgm_ops);
+CREATE INDEX department_name_trgm_idx ON department USING gin (name gin_trgm_ops);
+
 -- DROP VIEW student_score_course;
+-- DROP TABLE idempotency_key;
+-- DROP TABLE transcript;
+-- DROP TABLE score_history;
+-- DROP TABLE term;
 -- DROP TABLE score;
 -- DROP TABLE student;
 -- DROP TABLE course;
 -- DROP TABLE department;
--- DROP TABLE users;
\ No newline at end of file
+-- DROP TABLE users;
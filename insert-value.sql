@@ -188,4 +188,8 @@ INSERT INTO score VALUES ('170208','020401',null,null);
 INSERT INTO score VALUES ('170208','020402',null,null);
 INSERT INTO score VALUES ('170208','020501',null,null);
 
-INSERT INTO users VALUES ('Sylvan Raine', 'sylvan-raine', '123456');
+INSERT INTO users VALUES ('Sylvan Raine', 'sylvan-raine', '123456', 'admin');
+
+INSERT INTO term VALUES ('201501','2015-2016 学年第一学期',to_date('2015-09-01','yyyy-mm-dd'),to_date('2016-02-28','yyyy-mm-dd'),true);
+INSERT INTO term VALUES ('201502','2015-2016 学年第二学期',to_date('2016-02-29','yyyy-mm-dd'),to_date('2016-08-31','yyyy-mm-dd'),true);
+INSERT INTO term VALUES ('201601','2016-2017 学年第一学期',to_date('2016-09-01','yyyy-mm-dd'),to_date('2017-02-28','yyyy-mm-dd'),false);
@@ -0,0 +1,6 @@
+-- 为已有的数据库启用全站搜索 ( /api/search ), 新建的数据库直接执行 create-table.sql 即可
+-- psql -h 127.0.0.1 -U postgres -d postgres -f migrations/001-search.sql
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX IF NOT EXISTS student_name_trgm_idx ON student USING gin (name gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS course_name_trgm_idx ON course USING gin (name gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS department_name_trgm_idx ON department USING gin (name gin_trgm_ops);
@@ -0,0 +1,14 @@
+-- 为已有的数据库启用 Idempotency-Key, 新建的数据库直接执行 create-table.sql 即可
+-- psql -h 127.0.0.1 -U postgres -d postgres -f migrations/002-idempotency.sql
+-- 带有 Idempotency-Key 的 POST 请求及其响应, 重试时直接返回保存的响应, 过期的记录会被定期清理
+CREATE TABLE IF NOT EXISTS idempotency_key(
+    user_id                         VARCHAR(32)     NOT NULL,
+    key                             VARCHAR(255)    NOT NULL,
+    request_hash                    CHAR(64)        NOT NULL,
+    status                          INT,            -- 为空表示请求还在处理中
+    content_type                    VARCHAR(255),
+    body                            BYTEA,
+    created_at                      TIMESTAMP       NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY (user_id, key)
+);
+CREATE INDEX IF NOT EXISTS idempotency_key_created_at_idx ON idempotency_key (created_at);
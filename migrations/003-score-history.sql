@@ -0,0 +1,46 @@
+-- 为已有的数据库加上学期, 成绩历史, 成绩单和用户角色, 新建的数据库直接执行 create-table.sql 即可
+-- psql -h 127.0.0.1 -U postgres -d postgres -f migrations/003-score-history.sql
+-- 已有的用户都会成为教师 (teacher), 教务人员和管理员需要之后手动修改 users.role
+ALTER TABLE users ADD COLUMN IF NOT EXISTS
+    role        VARCHAR(16) NOT NULL DEFAULT 'teacher' CHECK (role IN('teacher','registrar','admin'));
+
+CREATE TABLE IF NOT EXISTS term(
+    id                              CHAR(6)         NOT NULL UNIQUE,
+    name                            VARCHAR(20)     NOT NULL,
+    start_date                      date            NOT NULL,
+    end_date                        date            NOT NULL,
+    closed                          BOOLEAN         NOT NULL DEFAULT FALSE,
+    PRIMARY KEY (id),
+    CHECK (start_date <= end_date)
+);
+
+-- 成绩的每一个版本都会保存在这里, 不引用 score 表, 以便成绩被删除后仍保留历史
+CREATE TABLE IF NOT EXISTS score_history(
+    id                              SERIAL          NOT NULL,
+    stu_id                          CHAR(6)         NOT NULL,
+    course_id                       CHAR(6)         NOT NULL,
+    old_score                       INT,
+    new_score                       INT,
+    reason                          VARCHAR(200),
+    status                          VARCHAR(16)     NOT NULL CHECK (status IN('direct','pending','approved','rejected')),
+    proposed_by                     VARCHAR(32)     NOT NULL,
+    proposed_at                     TIMESTAMP       NOT NULL DEFAULT current_timestamp,
+    reviewed_by                     VARCHAR(32),
+    reviewed_at                     TIMESTAMP,
+    review_comment                  VARCHAR(200),
+    PRIMARY KEY (id),
+    FOREIGN KEY (stu_id)            REFERENCES      student(id),
+    FOREIGN KEY (course_id)         REFERENCES      course(id)
+);
+CREATE INDEX IF NOT EXISTS score_history_score_idx ON score_history(stu_id, course_id);
+
+-- 每一份打印出去的成绩单都会在这里留下快照, 以便通过验证码核验真伪
+CREATE TABLE IF NOT EXISTS transcript(
+    code                            CHAR(16)        NOT NULL,
+    stu_id                          CHAR(6)         NOT NULL,
+    issued_by                       VARCHAR(32)     NOT NULL,
+    issued_at                       TIMESTAMP       NOT NULL DEFAULT current_timestamp,
+    content                         JSONB           NOT NULL,
+    PRIMARY KEY (code),
+    FOREIGN KEY (stu_id)            REFERENCES      student(id)
+);
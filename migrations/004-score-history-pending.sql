@@ -0,0 +1,4 @@
+-- 保证每条成绩最多只有一条待审批的修改申请, 新建的数据库直接执行 create-table.sql 即可
+-- psql -h 127.0.0.1 -U postgres -d postgres -f migrations/004-score-history-pending.sql
+-- 已经存在多条待审批申请的成绩需要先处理掉多余的申请, 否则创建索引会失败
+CREATE UNIQUE INDEX IF NOT EXISTS score_history_pending_idx ON score_history(stu_id, course_id) WHERE status = 'pending';
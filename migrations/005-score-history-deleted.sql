@@ -0,0 +1,5 @@
+-- 成绩被删除时也会在 score_history 中留下一条 deleted 记录, 新建的数据库直接执行 create-table.sql 即可
+-- psql -h 127.0.0.1 -U postgres -d postgres -f migrations/005-score-history-deleted.sql
+ALTER TABLE score_history DROP CONSTRAINT IF EXISTS score_history_status_check;
+ALTER TABLE score_history ADD CONSTRAINT score_history_status_check
+    CHECK (status IN('direct','pending','approved','rejected','deleted'));
@@ -1,17 +1,25 @@
+use crate::route::idempotency;
+use crate::route::request::events;
 use crate::server::ServerState;
-use crate::{database, logger, server};
+use crate::{app_config, database, grpc, logger, server};
 use axum::Router;
 
 /// 初始化 [logger]
 ///
 /// 和数据库连接 [database::init]
 ///
-/// 并使用传进来的 [Router] 启动服务器
+/// 并使用传进来的 [Router] 启动服务器, 同时在另一个端口启动 [grpc] 服务, 两者共用同一个 [ServerState]
 pub async fn run(router: Router<ServerState>) -> anyhow::Result<()> {
     logger::init();
     tracing::info!("正在启动服务器...");
     let db = database::init().await?;
+    if app_config::get_server().transcript_font().is_none() {
+        tracing::warn!("没有配置 transcript_font, 将无法生成 PDF 成绩单");
+    }
 
     let state = ServerState::new(db);
-    server::start(router, state).await
+    tokio::spawn(events::listen(state.clone()));
+    tokio::spawn(idempotency::purge(state.db().clone()));
+    tokio::try_join!(server::start(router, state.clone()), grpc::start(state))?;
+    Ok(())
 }
@@ -1,12 +1,20 @@
+use crate::error::ErrorFormat;
+use crate::i18n::Lang;
 use serde::Deserialize;
+use std::time::Duration;
 
 #[derive(Deserialize)]
 pub struct ServerConfig {
     port: Option<u16>,
+    grpc_port: Option<u16>,
     log_level: Option<String>,
     ipv4_enabled: Option<bool>,
     ipv6_enabled: Option<bool>,
     secret_key: Option<String>,
+    transcript_font: Option<String>,
+    idempotency_ttl_hours: Option<u64>,
+    error_format: Option<ErrorFormat>,
+    default_language: Option<Lang>,
 }
 
 impl ServerConfig {
@@ -14,6 +22,11 @@ impl ServerConfig {
         self.port.unwrap_or(80)
     }
 
+    /// gRPC 服务监听的端口, 和 HTTP 服务共用同一个监听地址
+    pub fn grpc_port(&self) -> u16 {
+        self.grpc_port.unwrap_or(50051)
+    }
+
     pub fn log_level(&self) -> &str {
         self.log_level.as_deref().unwrap_or("info")
     }
@@ -32,4 +45,24 @@ impl ServerConfig {
             .as_deref()
             .unwrap_or("ZGVmYXVsdCBzZWNyZXQga2V5IG9mIHdlYi1zdGFydGVy")
     }
+
+    /// 生成 PDF 成绩单所用的 TrueType 字体文件, 需要包含中文字形, 没有默认值
+    pub fn transcript_font(&self) -> Option<&str> {
+        self.transcript_font.as_deref()
+    }
+
+    /// Idempotency-Key 及其响应保存的时间, 过期之后同一个 key 可以被再次使用
+    pub fn idempotency_ttl(&self) -> Duration {
+        Duration::from_secs(self.idempotency_ttl_hours.unwrap_or(24) * 60 * 60)
+    }
+
+    /// 错误响应的格式, 默认为旧的 `legacy` 格式, 设置为 `problem` 时使用 RFC 7807 的 `application/problem+json`
+    pub fn error_format(&self) -> ErrorFormat {
+        self.error_format.unwrap_or_default()
+    }
+
+    /// 请求头 `Accept-Language` 中没有支持的语言时所用的语言, 默认为 `zh-CN`, 也可以设置为 `en`
+    pub fn default_language(&self) -> Lang {
+        self.default_language.unwrap_or_default()
+    }
 }
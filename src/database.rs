@@ -3,6 +3,8 @@ use sea_orm::{
     ConnectOptions, ConnectionTrait, Database, DatabaseConnection, DbBackend, Statement,
 };
 use std::cmp::max;
+use std::collections::HashMap;
+use std::sync::OnceLock;
 use std::time::Duration;
 use tracing::log;
 
@@ -33,6 +35,11 @@ pub async fn init() -> anyhow::Result<DatabaseConnection> {
     })?;
     tracing::info!("已与数据库建立连接.");
     log_database_version(&conn).await?;
+    if let Err(e) = load_constraints(&conn).await {
+        tracing::warn!(
+            "无法读取数据库中的约束定义, 违反约束时的错误消息中不会有字段名, 详细信息: {e}"
+        );
+    }
 
     Ok(conn)
 }
@@ -49,3 +56,46 @@ async fn log_database_version(db: &DatabaseConnection) -> anyhow::Result<()> {
     log::info!("数据库版本: {}", version.try_get_by_index::<String>(0)?);
     Ok(())
 }
+
+/// 各个约束 (包括唯一索引) 所涉及的字段, 以 `, ` 分隔, 见 [constraint_columns]
+static CONSTRAINTS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// 从系统目录中读取当前 schema 中所有约束和唯一索引所涉及的字段
+async fn load_constraints(db: &DatabaseConnection) -> anyhow::Result<()> {
+    let rows = db
+        .query_all(Statement::from_string(
+            DbBackend::Postgres,
+            r#"SELECT con.conname::text, string_agg(a.attname::text, ', ' ORDER BY k.ord)
+FROM pg_constraint con
+CROSS JOIN LATERAL unnest(con.conkey) WITH ORDINALITY AS k(attnum, ord)
+JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = k.attnum
+WHERE con.connamespace = current_schema()::regnamespace
+GROUP BY con.conname
+UNION
+SELECT c.relname::text, string_agg(a.attname::text, ', ' ORDER BY k.ord)
+FROM pg_index i
+JOIN pg_class c ON c.oid = i.indexrelid
+CROSS JOIN LATERAL unnest(i.indkey::int2[]) WITH ORDINALITY AS k(attnum, ord)
+JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = k.attnum
+WHERE i.indisunique AND c.relnamespace = current_schema()::regnamespace
+GROUP BY c.relname"#,
+        ))
+        .await?;
+    let constraints = rows
+        .iter()
+        .map(|row| {
+            Ok((
+                row.try_get_by_index::<String>(0)?,
+                row.try_get_by_index::<String>(1)?,
+            ))
+        })
+        .collect::<Result<HashMap<_, _>, sea_orm::DbErr>>()?;
+    tracing::info!("读取了 {} 个约束的定义.", constraints.len());
+    let _ = CONSTRAINTS.set(constraints);
+    Ok(())
+}
+
+/// 约束 `name` 所涉及的字段, 如 `score_pkey` 对应 `stu_id, course_id`. 没有读取到约束定义时返回 None
+pub fn constraint_columns(name: &str) -> Option<&'static str> {
+    CONSTRAINTS.get()?.get(name).map(String::as_str)
+}
@@ -1,9 +1,15 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.12
 
+use async_graphql::SimpleObject;
+use schemars::JsonSchema;
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
+#[derive(
+    Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize, JsonSchema, SimpleObject,
+)]
+#[schemars(rename = "Course")]
+#[graphql(name = "Course", complex)]
 #[sea_orm(table_name = "course")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
@@ -4,6 +4,11 @@ pub mod prelude;
 
 pub mod course;
 pub mod department;
+pub mod idempotency_key;
 pub mod score;
+pub mod score_history;
 pub mod student;
+pub mod student_score_course;
+pub mod term;
+pub mod transcript;
 pub mod users;
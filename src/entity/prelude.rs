@@ -2,6 +2,11 @@
 
 pub use super::course::Entity as Course;
 pub use super::department::Entity as Department;
+pub use super::idempotency_key::Entity as IdempotencyKey;
 pub use super::score::Entity as Score;
+pub use super::score_history::Entity as ScoreHistory;
 pub use super::student::Entity as Student;
+pub use super::student_score_course::Entity as StudentScoreCourse;
+pub use super::term::Entity as Term;
+pub use super::transcript::Entity as Transcript;
 pub use super::users::Entity as Users;
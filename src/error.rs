@@ -1,69 +1,276 @@
+use crate::{app_config, database, i18n, t};
 use axum::body::Body;
-use axum::http::StatusCode;
+use axum::extract::Request;
+use axum::http::{HeaderName, StatusCode, header};
+use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
-use serde::Serialize;
+use schemars::JsonSchema;
+use sea_orm::sqlx::postgres::PgDatabaseError;
+use sea_orm::{DbErr, RuntimeErr};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use validator::{ValidationErrors, ValidationErrorsKind};
 
+/// 各个变体对应的消息见消息目录中的 `error.<code>.message` 和 `error.<code>.title`, code 见 [AppError::code]
 #[derive(Debug, thiserror::Error, Serialize)]
 pub enum AppError {
-    #[error("服务器好像把它弄丢了. 😢 {0}")]
-    NotFound(String), // 404 Not Found
+    NotFound(String),             // 404 Not Found
+    MethodNotAllowed,             // 405 Method Not Allowed
+    BadRequest(String),           // 400 Bad Request
+    BadJson(String),              // 400 Bad Request
+    BadPath(String),              // 400 Bad Request
+    Unauthorized(String),         // 401 Unauthorized
+    Forbidden(String),            // 403 Forbidden
+    UnprocessableEntity(String),  // 422 Unprocessable Entity
+    Validation(ValidationErrors), // 422 Unprocessable Entity
+    Conflict(String),             // 409 Conflict
+    PreconditionFailed(String),   // 412 Precondition Failed
+    PreconditionRequired(String), // 428 Precondition Required
+    Internal(String),             // 500 服务器内部错误
+    Database(String),             // 500 数据库错误
+}
 
-    #[error("不要这样对我! 🥲")]
-    MethodNotAllowed, // 405 Method Not Allowed
+/// 出错时默认返回的响应体, 即 `error_format = "legacy"` 时的格式
+#[derive(Serialize, JsonSchema)]
+pub struct ErrorResponse {
+    /// 和 HTTP 状态码相同
+    status_code: u16,
+    /// 稳定的错误码, 见 [AppError::code]
+    code: &'static str,
+    message: String,
+}
 
-    #[error("你这请求是啥啊? 🤔 {0}")]
-    BadRequest(String), // 400 Bad Request
+/// RFC 7807 定义的 `application/problem+json` 响应体
+#[derive(Serialize, JsonSchema)]
+pub struct ProblemDetails {
+    /// 错误类型, 形如 `urn:web-start:error:not_found`
+    #[serde(rename = "type")]
+    kind: String,
+    /// 错误类型的简短描述, 同一类错误总是相同
+    title: String,
+    /// 和 HTTP 状态码相同
+    status: u16,
+    /// 这一次出错的具体原因
+    detail: String,
+    /// 请求 id, 和响应头 `x-request-id` 相同, 用于在日志中查找这次请求
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<String>,
+    /// 稳定的错误码, 见 [AppError::code]
+    code: &'static str,
+    /// 参数没有通过校验时, 每个字段的错误, 嵌套的字段形如 `items[0].score`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<BTreeMap<String, Vec<String>>>,
+}
 
-    #[error("你这 JSON 不对吧? 🤔 {0}")]
-    BadJson(String), // 400 Bad Request
+/// 错误响应的格式
+#[derive(Deserialize, Clone, Copy, PartialEq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorFormat {
+    /// `application/problem+json`, 需要在配置中指定, 或者由请求头 `Accept` 选择
+    Problem,
+    /// 只有 `status_code`, `code` 和 `message` 的旧格式, 和之前的客户端兼容
+    #[default]
+    Legacy,
+}
 
-    #[error("你的路径好像不对? 🤔 {0}")]
-    BadPath(String), // 400 Bad Request
+/// 生成错误响应时所需的请求信息
+#[derive(Clone)]
+struct ErrorContext {
+    request_id: Option<String>,
+    format: ErrorFormat,
+}
 
-    #[error("不是你谁啊, 先登录. 😢 {0}")]
-    Unauthorized(String), // 401 Unauthorized
+tokio::task_local! {
+    static CONTEXT: ErrorContext;
+}
 
-    #[error("你请求参数取值好像不对, 服务器没法处理. 😢 {0}")]
-    UnprocessableEntity(String), // 422 Unprocessable Entity
+/// 记录当前请求的 id 和错误响应的格式, 处理这个请求时产生的 [AppError] 都按照它生成响应
+///
+/// 格式由配置中的 `error_format` 决定, 请求头 `Accept` 中带有 `application/problem+json` 时总是使用 RFC 7807 的格式
+pub async fn error_context(request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let accepts_problem = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains(PROBLEM_JSON));
+    let context = ErrorContext {
+        request_id: headers
+            .get(&REQUEST_ID)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        format: if accepts_problem {
+            ErrorFormat::Problem
+        } else {
+            app_config::get_server().error_format()
+        },
+    };
+    CONTEXT.scope(context, next.run(request)).await
+}
 
-    #[error("坏了, 服务器出问题了... 😶 {0}")]
-    Internal(String), // 500 服务器内部错误
+const PROBLEM_JSON: &str = "application/problem+json";
 
-    #[error("数据库应该出问题了. 😍")]
-    Database(String), // 500 数据库错误
-}
+/// 每个请求的 id, 没有时由服务器生成
+pub static REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        #[derive(Serialize)]
-        struct ResponseStruct {
-            status_code: u16,
-            message: String,
+        // 不在 [error_context] 中时 (如测试中) 使用配置中的格式
+        let context = CONTEXT
+            .try_with(Clone::clone)
+            .unwrap_or_else(|_| ErrorContext {
+                request_id: None,
+                format: app_config::get_server().error_format(),
+            });
+        let status = self.status_code();
+        match context.format {
+            ErrorFormat::Problem => (
+                status,
+                [(header::CONTENT_TYPE, PROBLEM_JSON)],
+                axum::Json(self.problem(context.request_id)),
+            )
+                .into_response(),
+            ErrorFormat::Legacy => (
+                status,
+                axum::Json(ErrorResponse {
+                    status_code: status.as_u16(),
+                    code: self.code(),
+                    message: self.to_string(),
+                }),
+            )
+                .into_response(),
         }
-
-        (
-            self.status_code(),
-            axum::Json(ResponseStruct {
-                status_code: self.status_code().as_u16(),
-                message: self.to_string(),
-            }),
-        )
-            .into_response()
     }
 }
 
 impl AppError {
-    fn status_code(&self) -> StatusCode {
+    pub fn status_code(&self) -> StatusCode {
         use AppError::*;
         match self {
             NotFound(_) => StatusCode::NOT_FOUND,
             MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
             BadRequest(_) | BadJson(_) | BadPath(_) => StatusCode::BAD_REQUEST,
             Unauthorized(_) => StatusCode::UNAUTHORIZED,
-            UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Forbidden(_) => StatusCode::FORBIDDEN,
+            UnprocessableEntity(_) | Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Conflict(_) => StatusCode::CONFLICT,
+            PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
             Database(_) | Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    /// 稳定的错误码, 客户端应当根据它而不是 `message` 判断错误的类型, 已有的错误码不会再修改
+    pub fn code(&self) -> &'static str {
+        use AppError::*;
+        match self {
+            NotFound(_) => "not_found",
+            MethodNotAllowed => "method_not_allowed",
+            BadRequest(_) => "bad_request",
+            BadJson(_) => "bad_json",
+            BadPath(_) => "bad_path",
+            Unauthorized(_) => "unauthorized",
+            Forbidden(_) => "forbidden",
+            UnprocessableEntity(_) => "unprocessable_entity",
+            Validation(_) => "validation_failed",
+            Conflict(_) => "conflict",
+            PreconditionFailed(_) => "precondition_failed",
+            PreconditionRequired(_) => "precondition_required",
+            Internal(_) => "internal",
+            Database(_) => "database",
+        }
+    }
+
+    fn title(&self) -> String {
+        t!(format!("error.{}.title", self.code()))
+    }
+
+    /// 这一次出错的具体原因, 数据库的错误信息可能包含表结构等细节, 不返回给客户端
+    fn detail(&self) -> Option<String> {
+        use AppError::*;
+        match self {
+            NotFound(detail)
+            | BadRequest(detail)
+            | BadJson(detail)
+            | BadPath(detail)
+            | Unauthorized(detail)
+            | Forbidden(detail)
+            | UnprocessableEntity(detail)
+            | Conflict(detail)
+            | PreconditionFailed(detail)
+            | PreconditionRequired(detail)
+            | Internal(detail) => Some(detail.clone()),
+            Validation(errors) => {
+                let fields: Vec<String> = field_errors(errors)
+                    .into_iter()
+                    .map(|(field, messages)| format!("{field}: {}", messages.join(", ")))
+                    .collect();
+                Some(fields.join("; "))
+            }
+            MethodNotAllowed | Database(_) => None,
+        }
+    }
+
+    fn problem(&self, instance: Option<String>) -> ProblemDetails {
+        use AppError::*;
+        let title = self.title();
+        ProblemDetails {
+            kind: format!("urn:web-start:error:{}", self.code()),
+            status: self.status_code().as_u16(),
+            detail: self.detail().unwrap_or_else(|| title.clone()),
+            title,
+            instance,
+            code: self.code(),
+            errors: match self {
+                Validation(errors) => Some(field_errors(errors)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// 将 [ValidationErrors] 展开为字段到错误信息的映射
+fn field_errors(errors: &ValidationErrors) -> BTreeMap<String, Vec<String>> {
+    let mut fields = BTreeMap::new();
+    collect_field_errors(errors, "", &mut fields);
+    fields
+}
+
+fn collect_field_errors(
+    errors: &ValidationErrors,
+    prefix: &str,
+    fields: &mut BTreeMap<String, Vec<String>>,
+) {
+    for (field, kind) in errors.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{prefix}.{field}")
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => fields
+                .entry(path)
+                .or_default()
+                .extend(errors.iter().map(i18n::validation_message)),
+            ValidationErrorsKind::Struct(errors) => collect_field_errors(errors, &path, fields),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_field_errors(errors, &format!("{path}[{index}]"), fields);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    /// 带有前缀的完整消息, 即旧格式中的 `message`, 也用于 gRPC 和 GraphQL 的错误
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = t!(format!("error.{}.message", self.code()));
+        match self.detail() {
+            Some(detail) => write!(f, "{message} {detail}"),
+            None => f.write_str(&message),
+        }
+    }
 }
 
 impl From<anyhow::Error> for AppError {
@@ -94,20 +301,180 @@ impl From<axum_valid::ValidRejection<AppError>> for AppError {
     fn from(e: axum_valid::ValidRejection<AppError>) -> Self {
         use AppError::*;
         match e {
-            axum_valid::ValidRejection::Valid(v) => UnprocessableEntity(v.to_string()),
+            axum_valid::ValidRejection::Valid(v) => Validation(v),
             axum_valid::ValidRejection::Inner(i) => i,
         }
     }
 }
 
-impl From<sea_orm::DbErr> for AppError {
-    fn from(val: sea_orm::DbErr) -> Self {
-        AppError::Database(val.to_string())
+impl From<DbErr> for AppError {
+    fn from(val: DbErr) -> Self {
+        constraint_violation(&val).unwrap_or_else(|| AppError::Database(val.to_string()))
     }
 }
 
+/// 根据 PostgreSQL 的 SQLSTATE 将违反约束的错误转换为 409 或 422, 并指出违反的约束和字段
+///
+/// - 23505 唯一约束 (包括主键) 冲突: 409
+/// - 23503 外键约束: 422, 即引用的记录不存在. 删除或修改仍被引用的记录时为 409, 见 [still_referenced]
+/// - 23514 检查约束, 23502 非空约束: 422
+///
+/// 数据库返回的详细信息会随 `lc_messages` 翻译, 所以不从中解析任何内容,
+/// 约束所涉及的字段来自启动时读取的约束定义, 见 [database::constraint_columns].
+/// 其他错误返回 `None`, 仍然作为数据库错误处理
+fn constraint_violation(e: &DbErr) -> Option<AppError> {
+    let e = pg_error(e)?;
+    let constraint = e.constraint().unwrap_or_default();
+    let fields = database::constraint_columns(constraint).unwrap_or("?");
+
+    let error = match e.code() {
+        "23505" => AppError::Conflict(t!(
+            "db.unique_violation",
+            fields = fields,
+            constraint = constraint,
+        )),
+        "23503" => AppError::UnprocessableEntity(t!(
+            "db.foreign_key_violation",
+            fields = fields,
+            constraint = constraint,
+        )),
+        "23514" => match database::constraint_columns(constraint) {
+            Some(fields) => AppError::UnprocessableEntity(t!(
+                "db.check_violation",
+                fields = fields,
+                constraint = constraint,
+            )),
+            None => AppError::UnprocessableEntity(t!(
+                "db.check_violation_table",
+                constraint = constraint,
+            )),
+        },
+        "23502" => AppError::UnprocessableEntity(t!(
+            "db.not_null_violation",
+            field = e.column().unwrap_or("?"),
+        )),
+        _ => return None,
+    };
+    Some(error)
+}
+
+/// 删除或修改 `table` 中的一条记录时出错, 违反外键约束说明这条记录仍被其他记录引用, 返回 409
+///
+/// 修改时 (`table` 不为 None) 违反的如果是 `table` 自己的外键约束, 说明是新的值引用的记录不存在, 仍然返回 422.
+/// 删除时违反的外键约束一定属于引用这条记录的表 (可能就是 `table` 自己, 如 `course.pre_course`)
+pub fn still_referenced(e: DbErr, table: Option<&str>) -> AppError {
+    let Some(pg) = pg_error(&e).filter(|pg| pg.code() == "23503") else {
+        return e.into();
+    };
+    let referencing = pg.table().unwrap_or("?");
+    if table.is_some_and(|table| table == referencing) {
+        return e.into();
+    }
+    let constraint = pg.constraint().unwrap_or_default();
+    AppError::Conflict(t!(
+        "db.still_referenced",
+        table = referencing,
+        fields = database::constraint_columns(constraint).unwrap_or("?"),
+        constraint = constraint,
+    ))
+}
+
+fn pg_error(e: &DbErr) -> Option<&PgDatabaseError> {
+    let (DbErr::Query(RuntimeErr::SqlxError(e)) | DbErr::Exec(RuntimeErr::SqlxError(e))) = e else {
+        return None;
+    };
+    e.as_database_error()?.try_downcast_ref::<PgDatabaseError>()
+}
+
+/// 违反了的约束 (或唯一索引) 的名字, 用于为某个特定的约束给出更具体的错误消息
+pub fn violated_constraint(e: &DbErr) -> Option<&str> {
+    pg_error(e)?.constraint()
+}
+
 impl From<AppError> for axum::http::Response<Body> {
     fn from(value: AppError) -> Self {
         value.into_response()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use validator::Validate;
+
+    #[derive(Validate)]
+    struct Item {
+        #[validate(range(min = 0, max = 100, message = "成绩应该在 0 到 100 之间"))]
+        score: i32,
+    }
+
+    #[derive(Validate)]
+    struct Items {
+        #[validate(length(min = 1, message = "不能为空"))]
+        name: String,
+        #[validate(nested)]
+        items: Vec<Item>,
+    }
+
+    #[test]
+    fn test_problem_details() {
+        let items = Items {
+            name: String::new(),
+            items: vec![Item { score: 90 }, Item { score: 101 }],
+        };
+        let e = AppError::Validation(items.validate().unwrap_err());
+        assert_eq!(e.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let problem = serde_json::to_value(e.problem(Some("abc".into()))).unwrap();
+        assert_eq!(problem["type"], "urn:web-start:error:validation_failed");
+        assert_eq!(problem["status"], 422);
+        assert_eq!(problem["instance"], "abc");
+        assert_eq!(
+            problem["errors"],
+            serde_json::json!({
+                "items[1].score": ["成绩应该在 0 到 100 之间"],
+                "name": ["不能为空"],
+            })
+        );
+
+        let problem =
+            serde_json::to_value(AppError::Database("secret".into()).problem(None)).unwrap();
+        assert_eq!(problem["code"], "database");
+        assert!(!problem["detail"].as_str().unwrap().contains("secret"));
+        assert!(problem.get("instance").is_none());
+        assert!(problem.get("errors").is_none());
+    }
+
+    /// 每个错误码在消息目录中都有 title 和 message
+    #[test]
+    fn test_error_messages() {
+        let errors = [
+            AppError::NotFound(String::new()),
+            AppError::MethodNotAllowed,
+            AppError::BadRequest(String::new()),
+            AppError::BadJson(String::new()),
+            AppError::BadPath(String::new()),
+            AppError::Unauthorized(String::new()),
+            AppError::Forbidden(String::new()),
+            AppError::UnprocessableEntity(String::new()),
+            AppError::Validation(ValidationErrors::new()),
+            AppError::Conflict(String::new()),
+            AppError::PreconditionFailed(String::new()),
+            AppError::PreconditionRequired(String::new()),
+            AppError::Internal(String::new()),
+            AppError::Database(String::new()),
+        ];
+        for e in errors {
+            assert!(
+                !e.title().starts_with("error."),
+                "缺少 {} 的 title",
+                e.code()
+            );
+            assert!(
+                !e.to_string().starts_with("error."),
+                "缺少 {} 的 message",
+                e.code()
+            );
+        }
+    }
+}
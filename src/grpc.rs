@@ -0,0 +1,417 @@
+use crate::app_config;
+use crate::entity::prelude::{Course, Department, Score, Student};
+use crate::error::AppError;
+use crate::route::crud::{self, CrudResource};
+use crate::route::etag::{self, IfMatch};
+use crate::route::filter::Filters;
+use crate::route::middleware;
+use crate::route::page::PageParam;
+use crate::route::request::login::UserIdent;
+use crate::route::sort::SortParam;
+use crate::server::ServerState;
+use crate::t;
+use axum::http::StatusCode;
+use proto::course_service_server::{CourseService, CourseServiceServer};
+use proto::department_service_server::{DepartmentService, DepartmentServiceServer};
+use proto::score_service_server::{ScoreService, ScoreServiceServer};
+use proto::student_service_server::{StudentService, StudentServiceServer};
+use proto::{Ack, DeleteRequest, Id, ListRequest, ScoreDeleteRequest, ScoreKey};
+use sea_orm::IntoActiveModel;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use tonic::{Code, Request, Response, Status};
+
+/// 由 `proto/web_start.proto` 生成的消息和服务
+pub mod proto {
+    tonic::include_proto!("webstart.v1");
+
+    /// 编码之后的文件描述符, 用于服务反射
+    pub const FILE_DESCRIPTOR_SET: &[u8] =
+        include_bytes!(concat!(env!("OUT_DIR"), "/web_start_descriptor.bin"));
+}
+
+/// 在配置的 gRPC 端口上启动 gRPC 服务, 和 HTTP 服务共用同一个 [ServerState]
+///
+/// 除了服务反射之外, 所有的服务都和 HTTP 接口一样需要 JWT 鉴权
+pub async fn start(state: ServerState) -> anyhow::Result<()> {
+    let port = app_config::get_server().grpc_port();
+    let addr = if app_config::get_server().ipv6_enabled() {
+        SocketAddr::from((Ipv6Addr::UNSPECIFIED, port))
+    } else {
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))
+    };
+
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .build_v1()?;
+    let service = Grpc(state);
+
+    tracing::info!("gRPC 服务正在监听发往 {addr} 的请求.");
+    tonic::transport::Server::builder()
+        .add_service(reflection)
+        .add_service(StudentServiceServer::with_interceptor(
+            service.clone(),
+            authenticate,
+        ))
+        .add_service(CourseServiceServer::with_interceptor(
+            service.clone(),
+            authenticate,
+        ))
+        .add_service(DepartmentServiceServer::with_interceptor(
+            service.clone(),
+            authenticate,
+        ))
+        .add_service(ScoreServiceServer::with_interceptor(service, authenticate))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+/// 和 [`middleware::Auth`] 一样校验 metadata 中的 `authorization`, 通过之后把 [UserIdent] 放进请求的扩展中
+#[allow(clippy::result_large_err)] // 拦截器的签名由 tonic 决定
+fn authenticate(mut request: Request<()>) -> Result<Request<()>, Status> {
+    let auth_header = request
+        .metadata()
+        .get("authorization")
+        .map(|v| {
+            v.to_str()
+                .map_err(|e| AppError::BadRequest(t!("auth.bad_encoding", error = e)))
+        })
+        .transpose()?;
+    let usr = middleware::authenticate(auth_header)?;
+    request.extensions_mut().insert(usr);
+    Ok(request)
+}
+
+impl From<AppError> for Status {
+    fn from(e: AppError) -> Self {
+        let code = match e.status_code() {
+            StatusCode::NOT_FOUND => Code::NotFound,
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+            StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+            StatusCode::FORBIDDEN => Code::PermissionDenied,
+            StatusCode::CONFLICT => Code::Aborted,
+            StatusCode::PRECONDITION_FAILED | StatusCode::PRECONDITION_REQUIRED => {
+                Code::FailedPrecondition
+            }
+            StatusCode::METHOD_NOT_ALLOWED => Code::Unimplemented,
+            _ => Code::Internal,
+        };
+        Status::new(code, e.to_string())
+    }
+}
+
+/// proto 消息和实体, 参数的字段名都相同, 通过 serde 相互转换
+fn from_message<B: DeserializeOwned>(message: impl Serialize) -> Result<B, AppError> {
+    serde_json::to_value(message)
+        .and_then(serde_json::from_value)
+        .map_err(|e| AppError::BadRequest(t!("grpc.parse_message", error = e)))
+}
+
+fn to_message<B: DeserializeOwned>(value: impl Serialize) -> Result<B, AppError> {
+    serde_json::to_value(value)
+        .and_then(serde_json::from_value)
+        .map_err(|e| AppError::Internal(t!("grpc.build_message", error = e)))
+}
+
+/// 在记录转换成的消息中加上它的 etag, 和 REST 接口中按主键获取它时的 ETag 相同
+fn tagged(model: &impl Serialize) -> Result<serde_json::Value, AppError> {
+    let mut value = serde_json::to_value(model)
+        .map_err(|e| AppError::Internal(t!("grpc.build_message", error = e)))?;
+    value["etag"] = etag::of(model)?.into();
+    Ok(value)
+}
+
+fn usr<T>(request: &Request<T>) -> Result<&UserIdent, AppError> {
+    request
+        .extensions()
+        .get::<UserIdent>()
+        .ok_or_else(|| AppError::Unauthorized(t!("auth.not_logged_in")))
+}
+
+/// 各个 gRPC 服务的实现, 和 HTTP 接口共用 [ServerState] 中的数据库连接池
+#[derive(Clone)]
+struct Grpc(ServerState);
+
+impl Grpc {
+    /// 和 REST 的 query 接口使用同一套筛选, 排序和分页的规则, 分页参数为 0 时使用默认值
+    async fn list<R: CrudResource, P: DeserializeOwned>(
+        &self,
+        request: Request<ListRequest>,
+    ) -> Result<Response<P>, Status> {
+        let request = request.into_inner();
+        let filters = Filters::new(request.filters.into_iter().map(|f| {
+            let op = if f.op.is_empty() {
+                "eq".to_string()
+            } else {
+                f.op
+            };
+            (f.field, op, f.value)
+        }));
+        let sort = SortParam::parse(&request.sort);
+        let default = PageParam::default();
+        let page = PageParam {
+            index: if request.index == 0 {
+                default.index
+            } else {
+                request.index
+            },
+            size: if request.size == 0 {
+                default.size
+            } else {
+                request.size
+            },
+        };
+
+        let page = crud::search::<R>(self.0.db(), filters, sort, page).await?;
+        let items = page.items.iter().map(tagged).collect::<Result<_, _>>()?;
+        let mut page = serde_json::to_value(page)
+            .map_err(|e| AppError::Internal(t!("grpc.build_message", error = e)))?;
+        page["items"] = serde_json::Value::Array(items);
+        Ok(Response::new(to_message(page)?))
+    }
+
+    async fn get<R: CrudResource, P: DeserializeOwned>(
+        &self,
+        key: R::Key,
+    ) -> Result<Response<P>, Status> {
+        let item = crud::find::<R>(self.0.db(), key).await?;
+        Ok(Response::new(to_message(tagged(&item)?)?))
+    }
+
+    /// 和 insert 接口走同一套校验, 权限检查和插入逻辑
+    async fn create<R: CrudResource>(
+        &self,
+        request: Request<impl Serialize>,
+    ) -> Result<Response<Ack>, Status> {
+        let usr = usr(&request)?.clone();
+        let params: R::Insert = from_message(request.into_inner())?;
+        crud::validate(&params)?;
+        crud::create::<R>(self.0.db(), &usr, params).await?;
+        Ok(Response::new(Ack {
+            message: t!("crud.inserted", entity = R::NAME),
+        }))
+    }
+
+    /// 和 PUT 更新接口走同一套校验, 权限检查, 版本检查和更新前后的检查, 以 `key` 为准
+    async fn update<R: CrudResource, P: Serialize + DeserializeOwned>(
+        &self,
+        key: R::Key,
+        etag: &str,
+        request: Request<P>,
+    ) -> Result<Response<P>, Status> {
+        let if_match = IfMatch::from_etag(etag)?;
+        let usr = usr(&request)?.clone();
+        let params: R::Update = from_message(request.into_inner())?;
+        crud::validate(&params)?;
+        let changes = params.into_active_model();
+        let updated = crud::save::<R>(self.0.db(), &usr, key, changes, &if_match).await?;
+        Ok(Response::new(to_message(tagged(&updated)?)?))
+    }
+
+    /// 和 DELETE 接口一样, 删除之前检查 `etag`
+    async fn delete<R: CrudResource, T>(
+        &self,
+        key: R::Key,
+        etag: &str,
+        request: Request<T>,
+    ) -> Result<Response<Ack>, Status> {
+        let if_match = IfMatch::from_etag(etag)?;
+        crud::remove::<R>(self.0.db(), usr(&request)?, key, &if_match).await?;
+        Ok(Response::new(Ack {
+            message: t!("crud.deleted", entity = R::NAME),
+        }))
+    }
+}
+
+#[tonic::async_trait]
+impl StudentService for Grpc {
+    async fn list(
+        &self,
+        request: Request<ListRequest>,
+    ) -> Result<Response<proto::StudentPage>, Status> {
+        self.list::<Student, _>(request).await
+    }
+
+    async fn get(&self, request: Request<Id>) -> Result<Response<proto::Student>, Status> {
+        self.get::<Student, _>(request.into_inner().id).await
+    }
+
+    async fn create(&self, request: Request<proto::Student>) -> Result<Response<Ack>, Status> {
+        self.create::<Student>(request).await
+    }
+
+    async fn update(
+        &self,
+        request: Request<proto::Student>,
+    ) -> Result<Response<proto::Student>, Status> {
+        let proto::Student { id, etag, .. } = request.get_ref().clone();
+        self.update::<Student, _>(id, &etag, request).await
+    }
+
+    async fn delete(&self, request: Request<DeleteRequest>) -> Result<Response<Ack>, Status> {
+        let DeleteRequest { id, etag } = request.get_ref().clone();
+        self.delete::<Student, _>(id, &etag, request).await
+    }
+}
+
+#[tonic::async_trait]
+impl CourseService for Grpc {
+    async fn list(
+        &self,
+        request: Request<ListRequest>,
+    ) -> Result<Response<proto::CoursePage>, Status> {
+        self.list::<Course, _>(request).await
+    }
+
+    async fn get(&self, request: Request<Id>) -> Result<Response<proto::Course>, Status> {
+        self.get::<Course, _>(request.into_inner().id).await
+    }
+
+    async fn create(&self, request: Request<proto::Course>) -> Result<Response<Ack>, Status> {
+        self.create::<Course>(request).await
+    }
+
+    async fn update(
+        &self,
+        request: Request<proto::Course>,
+    ) -> Result<Response<proto::Course>, Status> {
+        let proto::Course { id, etag, .. } = request.get_ref().clone();
+        self.update::<Course, _>(id, &etag, request).await
+    }
+
+    async fn delete(&self, request: Request<DeleteRequest>) -> Result<Response<Ack>, Status> {
+        let DeleteRequest { id, etag } = request.get_ref().clone();
+        self.delete::<Course, _>(id, &etag, request).await
+    }
+}
+
+#[tonic::async_trait]
+impl DepartmentService for Grpc {
+    async fn list(
+        &self,
+        request: Request<ListRequest>,
+    ) -> Result<Response<proto::DepartmentPage>, Status> {
+        self.list::<Department, _>(request).await
+    }
+
+    async fn get(&self, request: Request<Id>) -> Result<Response<proto::Department>, Status> {
+        self.get::<Department, _>(request.into_inner().id).await
+    }
+
+    async fn create(&self, request: Request<proto::Department>) -> Result<Response<Ack>, Status> {
+        self.create::<Department>(request).await
+    }
+
+    async fn update(
+        &self,
+        request: Request<proto::Department>,
+    ) -> Result<Response<proto::Department>, Status> {
+        let proto::Department { id, etag, .. } = request.get_ref().clone();
+        self.update::<Department, _>(id, &etag, request).await
+    }
+
+    async fn delete(&self, request: Request<DeleteRequest>) -> Result<Response<Ack>, Status> {
+        let DeleteRequest { id, etag } = request.get_ref().clone();
+        self.delete::<Department, _>(id, &etag, request).await
+    }
+}
+
+#[tonic::async_trait]
+impl ScoreService for Grpc {
+    async fn list(
+        &self,
+        request: Request<ListRequest>,
+    ) -> Result<Response<proto::ScorePage>, Status> {
+        self.list::<Score, _>(request).await
+    }
+
+    async fn get(&self, request: Request<ScoreKey>) -> Result<Response<proto::Score>, Status> {
+        let key = request.into_inner();
+        self.get::<Score, _>((key.stu_id, key.course_id)).await
+    }
+
+    async fn create(&self, request: Request<proto::Score>) -> Result<Response<Ack>, Status> {
+        self.create::<Score>(request).await
+    }
+
+    async fn update(
+        &self,
+        request: Request<proto::Score>,
+    ) -> Result<Response<proto::Score>, Status> {
+        let proto::Score {
+            stu_id,
+            course_id,
+            etag,
+            ..
+        } = request.get_ref().clone();
+        self.update::<Score, _>((stu_id, course_id), &etag, request)
+            .await
+    }
+
+    async fn delete(&self, request: Request<ScoreDeleteRequest>) -> Result<Response<Ack>, Status> {
+        let ScoreDeleteRequest {
+            stu_id,
+            course_id,
+            etag,
+        } = request.get_ref().clone();
+        self.delete::<Score, _>((stu_id, course_id), &etag, request)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::route::jwt::Jwt;
+    use crate::route::request::login::Role;
+
+    #[test]
+    fn test_authenticate() {
+        let status = authenticate(Request::new(())).unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", "Token abc".parse().unwrap());
+        assert_eq!(
+            authenticate(request).unwrap_err().code(),
+            Code::InvalidArgument
+        );
+
+        let token = Jwt::generate(UserIdent {
+            id: "admin".to_string(),
+            name: "管理员".to_string(),
+            role: Role::Admin,
+        });
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {token}").parse().unwrap());
+        let request = authenticate(request).unwrap();
+        assert_eq!(request.extensions().get::<UserIdent>().unwrap().id, "admin");
+    }
+
+    #[tokio::test]
+    async fn test_etag_required() {
+        let service = Grpc(ServerState::new(sea_orm::DatabaseConnection::Disconnected));
+        let request = Request::new(DeleteRequest {
+            id: "170101".to_string(),
+            etag: String::new(),
+        });
+        let status = StudentService::delete(&service, request).await.unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+
+        // 消息中的 etag 和 REST 接口中按主键获取时的 ETag 相同
+        let department = crate::entity::department::Model {
+            id: "01".to_string(),
+            name: Some("信息学院".to_string()),
+            office_room: None,
+            home_page: None,
+        };
+        let message: proto::Department = to_message(tagged(&department).unwrap()).unwrap();
+        assert_eq!(message.etag, etag::of(&department).unwrap());
+    }
+}
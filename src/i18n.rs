@@ -0,0 +1,290 @@
+use crate::app_config;
+use axum::extract::Request;
+use axum::http::{HeaderValue, header};
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use validator::ValidationError;
+
+/// 按消息的 key 取出当前请求所用语言的消息, `{name}` 会被替换为对应参数的值
+///
+/// ```ignore
+/// t!("crud.not_found", entity = R::NAME)
+/// ```
+#[macro_export]
+macro_rules! t {
+    ($key: expr $(, $name: ident = $value: expr)* $(,)?) => {
+        $crate::i18n::translate(&$key, &[$((stringify!($name), $value.to_string())),*])
+    };
+}
+
+/// 支持的语言, 简体中文的消息目录是最完整的, 其他语言缺少某条消息时使用简体中文
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Lang {
+    #[default]
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+    #[serde(rename = "en")]
+    En,
+}
+
+type Catalog = HashMap<String, String>;
+
+static ZH_CN: LazyLock<Catalog> = LazyLock::new(|| parse(include_str!("../locales/zh-CN.json")));
+static EN: LazyLock<Catalog> = LazyLock::new(|| parse(include_str!("../locales/en.json")));
+
+fn parse(catalog: &str) -> Catalog {
+    serde_json::from_str(catalog).expect("消息目录应该是一个值为字符串的 JSON 对象.")
+}
+
+impl Lang {
+    /// 语言标签, 用于响应头 `Content-Language`
+    pub fn tag(self) -> &'static str {
+        match self {
+            Lang::ZhCn => "zh-CN",
+            Lang::En => "en",
+        }
+    }
+
+    fn catalog(self) -> &'static Catalog {
+        match self {
+            Lang::ZhCn => &ZH_CN,
+            Lang::En => &EN,
+        }
+    }
+
+    /// 只比较主标签, 如 `zh-TW` 和 `zh-Hans` 都使用简体中文, `en-US` 使用英文
+    fn from_tag(tag: &str) -> Option<Lang> {
+        let primary = tag.split('-').next()?;
+        if primary.eq_ignore_ascii_case("zh") {
+            Some(Lang::ZhCn)
+        } else if primary.eq_ignore_ascii_case("en") {
+            Some(Lang::En)
+        } else {
+            None
+        }
+    }
+}
+
+tokio::task_local! {
+    static LANG: Lang;
+}
+
+/// 当前请求所用的语言, 不在请求中时 (如 gRPC) 使用配置的默认语言
+pub fn current() -> Lang {
+    LANG.try_with(|lang| *lang)
+        .unwrap_or_else(|_| app_config::get_server().default_language())
+}
+
+/// 以 `lang` 运行 `future`, 用于在请求之外 (如 WebSocket 连接中) 沿用请求的语言
+pub async fn scope<F: Future>(lang: Lang, future: F) -> F::Output {
+    LANG.scope(lang, future).await
+}
+
+/// 根据请求头 `Accept-Language` 选择这个请求所用的语言, 并在响应头 `Content-Language` 中返回
+pub async fn negotiate(request: Request, next: Next) -> Response {
+    let default = app_config::get_server().default_language();
+    let lang = request
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| preferred(v, default))
+        .unwrap_or(default);
+    let mut response = LANG.scope(lang, next.run(request)).await;
+    response.headers_mut().insert(
+        header::CONTENT_LANGUAGE,
+        HeaderValue::from_static(lang.tag()),
+    );
+    response
+}
+
+/// 按 `q` 值从高到低找出第一个支持的语言, `*` 表示默认语言
+fn preferred(header: &str, default: Lang) -> Option<Lang> {
+    let mut ranges: Vec<(&str, f32)> = header
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let tag = parts.next().filter(|tag| !tag.is_empty())?;
+            let q = match parts.find_map(|part| part.strip_prefix("q=")) {
+                Some(q) => q.parse().ok()?,
+                None => 1.0,
+            };
+            Some((tag, q))
+        })
+        .filter(|(_, q)| *q > 0.0)
+        .collect();
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges.into_iter().find_map(|(tag, _)| match tag {
+        "*" => Some(default),
+        tag => Lang::from_tag(tag),
+    })
+}
+
+fn lookup(lang: Lang, key: &str) -> Option<&'static String> {
+    lang.catalog()
+        .get(key)
+        .or_else(|| Lang::ZhCn.catalog().get(key))
+}
+
+/// 见 [t]
+pub fn translate(key: &str, args: &[(&str, String)]) -> String {
+    let Some(template) = lookup(current(), key) else {
+        tracing::warn!("消息目录中没有 {key}");
+        return key.to_string();
+    };
+    args.iter().fold(template.clone(), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+/// `validator` 的错误消息
+///
+/// 依次使用 `validation.<code>`, 属性中的 `message`, `validation.<code>.<between|min|max>` (取决于参数中有没有 min 和 max)
+/// 和 `validation.invalid`. 参数 (如 min, max 和 value) 可以在消息中使用
+pub fn validation_message(error: &ValidationError) -> String {
+    let args: Vec<(&str, String)> = error
+        .params
+        .iter()
+        .map(|(name, value)| {
+            let value = match value {
+                serde_json::Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            (name.as_ref(), value)
+        })
+        .collect();
+
+    let lang = current();
+    let key = format!("validation.{}", error.code);
+    if lookup(lang, &key).is_some() {
+        return translate(&key, &args);
+    }
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+    let bounds = match (
+        error.params.contains_key("min"),
+        error.params.contains_key("max"),
+    ) {
+        (true, true) => "between",
+        (true, false) => "min",
+        (false, true) => "max",
+        (false, false) => "",
+    };
+    let key = format!("validation.{}.{bounds}", error.code);
+    if lookup(lang, &key).is_some() {
+        translate(&key, &args)
+    } else {
+        translate("validation.invalid", &args)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// 消息中的所有 `{name}` 参数
+    fn placeholders(text: &str) -> BTreeSet<&str> {
+        text.split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}').map(|(name, _)| name))
+            .collect()
+    }
+
+    #[test]
+    fn test_catalogs_complete() {
+        for lang in [Lang::ZhCn, Lang::En] {
+            let catalog = lang.catalog();
+            for (key, text) in Lang::ZhCn.catalog() {
+                let translated = catalog
+                    .get(key)
+                    .unwrap_or_else(|| panic!("{} 的消息目录中缺少 {key}", lang.tag()));
+                assert_eq!(
+                    placeholders(text),
+                    placeholders(translated),
+                    "{} 中 {key} 的参数和 zh-CN 不同",
+                    lang.tag()
+                );
+            }
+            for key in catalog.keys() {
+                assert!(
+                    Lang::ZhCn.catalog().contains_key(key),
+                    "{} 的消息目录中多了 {key}",
+                    lang.tag()
+                );
+            }
+        }
+    }
+
+    /// 代码中通过 `t!` 和 `#[validate(code)]` 用到的 key 都应该在消息目录中
+    #[test]
+    fn test_keys_used_exist() {
+        fn visit(dir: &std::path::Path, keys: &mut Vec<String>) {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    visit(&path, keys);
+                } else if path.extension().is_some_and(|ext| ext == "rs") {
+                    let source = std::fs::read_to_string(&path).unwrap();
+                    for (start, _) in source.match_indices("t!(") {
+                        // 跳过 assert!( 等以 t 结尾的宏, 以及这里的字符串
+                        let before = source[..start].chars().next_back();
+                        if before.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '"') {
+                            continue;
+                        }
+                        let rest = source[start + 3..].trim_start();
+                        if let Some(key) = rest.strip_prefix('"') {
+                            keys.push(key.split('"').next().unwrap().to_string());
+                        }
+                    }
+                    // #[validate(...)] 中的 code 对应 validation.<code>
+                    for (start, _) in source.match_indices("code = \"") {
+                        let code = source[start + 8..].split('"').next().unwrap();
+                        keys.push(format!("validation.{code}"));
+                    }
+                }
+            }
+        }
+        let mut keys = Vec::new();
+        visit(
+            &std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src"),
+            &mut keys,
+        );
+        assert!(!keys.is_empty());
+        for key in keys {
+            assert!(
+                Lang::ZhCn.catalog().contains_key(&key),
+                "消息目录中没有 {key}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_accept_language() {
+        assert_eq!(preferred("en-US,en;q=0.9", Lang::ZhCn), Some(Lang::En));
+        assert_eq!(
+            preferred("fr;q=1, zh-TW;q=0.8, en;q=0.5", Lang::En),
+            Some(Lang::ZhCn)
+        );
+        assert_eq!(preferred("en;q=0, *", Lang::ZhCn), Some(Lang::ZhCn));
+        assert_eq!(preferred("fr, de", Lang::ZhCn), None);
+    }
+
+    #[test]
+    fn test_translate() {
+        let text = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(scope(Lang::En, async {
+                let mut error = ValidationError::new("range");
+                error.add_param("min".into(), &0);
+                error.add_param("max".into(), &100);
+                validation_message(&error)
+            }));
+        assert!(text.contains("0") && text.contains("100"), "{text}");
+        assert!(text.is_ascii(), "{text}");
+    }
+}
@@ -3,6 +3,8 @@ mod app_config;
 mod database;
 mod entity;
 mod error;
+mod grpc;
+mod i18n;
 mod logger;
 mod route;
 mod server;
@@ -0,0 +1,505 @@
+use crate::error::{self, AppError};
+use crate::route::etag::{self, IfMatch};
+use crate::route::export::{self, ExportFormat};
+use crate::route::extract::{Path, ValidJson, ValidQuery};
+use crate::route::fields::{self, FieldsParam};
+use crate::route::filter::Filters;
+use crate::route::include::{self, Embedded, Include, IncludeParam};
+use crate::route::openapi::{ApiDoc, Operation};
+use crate::route::page::{CursorParam, Page, PageParam};
+use crate::route::request::events::{self, Action, Topic};
+use crate::route::request::import::Importable;
+use crate::route::request::login::{Role, UserIdent};
+use crate::route::result::AppResult;
+use crate::route::routes::Routes;
+use crate::route::sort::SortParam;
+use crate::route::version::{ApiVersion, Deprecated};
+use crate::server::ServerState;
+use crate::t;
+use crate::throw_err;
+use axum::Extension;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use schemars::JsonSchema;
+use sea_orm::{
+    ActiveModelBehavior, ActiveModelTrait, ActiveValue, DatabaseConnection, DatabaseTransaction,
+    FromQueryResult, IdenStatic, IntoActiveModel, Iterable, ModelTrait, PrimaryKeyToColumn,
+    PrimaryKeyTrait, QueryFilter, QuerySelect, Select, TransactionTrait,
+};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use validator::Validate;
+
+/// 所有 query 接口共用的参数, 和各资源自己的筛选参数分开解析
+#[derive(Deserialize, Validate, JsonSchema)]
+pub struct ListParams {
+    /// 指定时导出全部结果, 而不是返回一页
+    pub format: Option<ExportFormat>,
+
+    /// 要嵌入到结果中的关联数据, 导出时会被忽略
+    #[serde(default)]
+    pub include: IncludeParam,
+
+    /// 排序的字段, 形如 `sort=-score,record_date`
+    #[serde(default)]
+    pub sort: SortParam,
+
+    #[validate(nested)]
+    #[serde(flatten)]
+    pub page: PageParam,
+
+    /// 结果中只需要的字段, 不能和 include 一起使用, 导出时会被忽略
+    #[serde(default)]
+    pub fields: FieldsParam,
+
+    /// 出现时使用游标分页, 此时 `page` 会被忽略
+    #[validate(nested)]
+    #[serde(flatten)]
+    pub cursor: CursorParam,
+}
+
+/// 按主键获取一条记录时的参数
+#[derive(Deserialize, Validate, JsonSchema)]
+pub struct GetParams {
+    #[serde(default)]
+    pub include: IncludeParam,
+
+    #[serde(default)]
+    pub fields: FieldsParam,
+}
+
+/// 可以自动生成增删查改路由的资源
+///
+/// 实现这个 trait 的实体只需要声明插入, 更新, 筛选所用的参数类型和筛选条件, [router] 会生成下面这些路由,
+/// 其中 `{key}` 为 [CrudResource::KEY] :
+///
+/// | 方法   | 路径              | 说明                          |
+/// |--------|-------------------|-------------------------------|
+/// | GET    | `/`               | 首页                          |
+/// | GET    | `/query`          | 分页查询或导出                |
+/// | POST   | `/insert`         | 插入一条记录                  |
+/// | GET    | `/{key}`          | 按主键获取, 支持 ETag         |
+/// | PATCH  | `/{key}`          | 部分更新                      |
+/// | PUT    | `/update/{key}`   | 更新                          |
+/// | DELETE | `/delete/{key}`   | 删除                          |
+pub trait CrudResource:
+    Include<
+        Model: DeserializeOwned + FromQueryResult + IntoActiveModel<Self::ActiveModel> + JsonSchema,
+        ActiveModel: ActiveModelBehavior + From<Self::Model> + Send + Sync,
+    >
+{
+    /// 资源的名字, 用于日志和返回的信息, 小写之后作为导出的文件名
+    const NAME: &'static str;
+
+    /// 增删改之后发出的变更通知中的实体, 见 [`events`]
+    const TOPIC: Topic;
+
+    /// 主键在路径中的写法, 如 `{id}` 或 `{stu_id}/{course_id}`
+    const KEY: &'static str = "{id}";
+
+    /// 可以通过 `filter[字段][操作]=值` 筛选的列, 不在其中的字段会返回 400
+    const FILTERABLE: &'static [Self::Column];
+
+    /// 可以通过 `sort` 参数排序的列, 主键总会作为最后的排序依据
+    const SORTABLE: &'static [Self::Column];
+
+    /// 写操作 (插入, 更新, 删除) 需要的角色, None 表示登录了就可以
+    const WRITE_ROLES: Option<&'static [Role]> = None;
+
+    /// 从路径中解析出来的主键
+    type Key: DeserializeOwned + Into<<Self::PrimaryKey as PrimaryKeyTrait>::ValueType> + Send;
+
+    /// 插入时的参数, 和表格导入共用同一套校验和插入逻辑
+    type Insert: Importable + JsonSchema;
+
+    /// PUT 更新时的参数, 其中的主键会被忽略
+    type Update: DeserializeOwned
+        + Validate
+        + IntoActiveModel<Self::ActiveModel>
+        + JsonSchema
+        + Send;
+
+    /// PATCH 部分更新时的参数, 只有被 Set 的字段会被更新
+    type Patch: DeserializeOwned + Validate + IntoActiveModel<Self::ActiveModel> + JsonSchema + Send;
+
+    /// query 接口的筛选参数
+    type Filter: DeserializeOwned + Validate + JsonSchema + Send;
+
+    /// 把筛选参数转换为查询条件, 排序会在之后按 `sort` 参数和主键进行
+    fn filter(select: Select<Self>, filter: Self::Filter) -> Select<Self>;
+
+    /// 更新之前的检查, 在更新所在的事务中执行, `changes` 是合并之后将要写入的记录
+    fn before_update(
+        _txn: &DatabaseTransaction,
+        _current: &Self::Model,
+        _changes: &Self::ActiveModel,
+    ) -> impl Future<Output = Result<(), AppError>> + Send {
+        async { Ok(()) }
+    }
+
+    /// 删除之前的检查, 在删除所在的事务中执行, 此时记录已经被锁住, 也可以用来留下删除记录
+    fn before_delete(
+        _txn: &DatabaseTransaction,
+        _current: &Self::Model,
+        _usr: &UserIdent,
+    ) -> impl Future<Output = Result<(), AppError>> + Send {
+        async { Ok(()) }
+    }
+
+    /// 更新之后, 事务提交之前执行, 可以用来留下修改记录
+    fn after_update(
+        _txn: &DatabaseTransaction,
+        _old: &Self::Model,
+        _new: &Self::Model,
+        _usr: &UserIdent,
+    ) -> impl Future<Output = Result<(), AppError>> + Send {
+        async { Ok(()) }
+    }
+}
+
+/// v1 中 RPC 风格的 `/update/{id}` 和 `/delete/{id}`, 在 v2 中被 `PUT /{id}` 和 `DELETE /{id}` 取代
+const RPC_STYLE: Deprecated = Deprecated::since(2026, 10, 19).sunset(2027, 10, 19);
+
+/// 为资源 `R` 生成增删查改的路由, 各模块可以在此基础上追加自己特有的路由
+pub fn router<R: CrudResource>(version: ApiVersion) -> Routes {
+    let by_key = format!("/{}", R::KEY);
+    let routes = Routes::new()
+        .get("/", index::<R>)
+        .get("/query", query::<R>)
+        .post("/insert", insert::<R>)
+        .get(&by_key, get_by_id::<R>)
+        .patch(&by_key, partial_update::<R>);
+    match version {
+        ApiVersion::V1 => routes
+            .put(&format!("/update/{}", R::KEY), update::<R>)
+            .deprecated(RPC_STYLE)
+            .delete(&format!("/delete/{}", R::KEY), delete::<R>)
+            .deprecated(RPC_STYLE),
+        ApiVersion::V2 => routes
+            .put(&by_key, update::<R>)
+            .delete(&by_key, delete::<R>),
+    }
+}
+
+/// 为资源 `R` 生成和 [router] 对应的接口文档
+pub fn document<R: CrudResource>(doc: &mut ApiDoc) {
+    let name = R::NAME;
+    let sortable: Vec<_> = R::SORTABLE.iter().map(|c| c.as_str()).collect();
+    doc.get(
+        "/",
+        Operation::new(format!("{name} 的首页")).returns::<String>(),
+    )
+    .get(
+        "/query",
+        Operation::new(format!("分页查询或导出 {name}"))
+            .description(format!(
+                "可以排序的字段有: {}. 指定 `format` 参数或 Accept 请求头时导出全部结果; \
+                     出现 `after`, `before` 或 `limit` 时返回游标分页的结果; \
+                     指定 `fields` 时每条记录只包含所选的字段.",
+                sortable.join(", ")
+            ))
+            .query::<ListParams>()
+            .query::<R::Filter>()
+            .filter(R::FILTERABLE)
+            .returns::<Page<Embedded<R::Model>>>(),
+    )
+    .post(
+        "/insert",
+        Operation::new(format!("添加一条 {name} 记录"))
+            .json::<R::Insert>()
+            .returns::<String>(),
+    )
+    .get(
+        &format!("/{}", R::KEY),
+        Operation::new(format!("按主键获取一条 {name} 记录"))
+            .description("支持 If-None-Match 条件请求, 内容没有变化时返回 304.")
+            .query::<GetParams>()
+            .returns::<Embedded<R::Model>>(),
+    )
+    .patch(
+        &format!("/{}", R::KEY),
+        Operation::new(format!("部分更新一条 {name} 记录"))
+            .description("只有请求中出现了的字段会被更新, 字段为 null 时清空该字段.")
+            .if_match()
+            .json::<R::Patch>()
+            .returns::<R::Model>(),
+    );
+    let update = Operation::new(format!("更新一条 {name} 记录"))
+        .if_match()
+        .json::<R::Update>()
+        .returns::<R::Model>();
+    let delete = Operation::new(format!("删除一条 {name} 记录"))
+        .if_match()
+        .returns::<String>();
+    match doc.version() {
+        ApiVersion::V1 => doc
+            .put(&format!("/update/{}", R::KEY), update.deprecated(RPC_STYLE))
+            .delete(&format!("/delete/{}", R::KEY), delete.deprecated(RPC_STYLE)),
+        ApiVersion::V2 => doc
+            .put(&format!("/{}", R::KEY), update)
+            .delete(&format!("/{}", R::KEY), delete),
+    };
+}
+
+fn not_found<R: CrudResource>() -> AppError {
+    AppError::NotFound(t!("crud.not_found", entity = R::NAME))
+}
+
+/// 检查 `usr` 是否可以对资源 `R` 进行写操作, 见 [CrudResource::WRITE_ROLES]
+pub(crate) fn authorize<R: CrudResource>(usr: &UserIdent) -> Result<(), AppError> {
+    match R::WRITE_ROLES {
+        Some(roles) => usr.require(roles),
+        None => Ok(()),
+    }
+}
+
+/// 路由到资源的默认界面
+async fn index<R: CrudResource>() -> AppResult<String> {
+    AppResult::Ok(t!("crud.welcome", entity = R::NAME))
+}
+
+/// 处理资源的查询请求, 通过 format 参数或者 Accept 请求头可以导出全部的结果
+async fn query<R: CrudResource>(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    ValidQuery(list): ValidQuery<ListParams>,
+    ValidQuery(filter): ValidQuery<R::Filter>,
+    filters: Filters,
+) -> Response {
+    tracing::debug!("开始处理: 查询 {}", R::NAME);
+    let condition = match filters.condition(R::FILTERABLE) {
+        Ok(condition) => condition,
+        Err(e) => return e.into_response(),
+    };
+    let select = R::filter(R::find(), filter).filter(condition);
+    let select = match list.sort.apply(select, R::SORTABLE) {
+        Ok(select) => select,
+        Err(e) => return e.into_response(),
+    };
+
+    if let Some(format) = ExportFormat::negotiate(list.format, &headers) {
+        return export::export(select, state.db(), format, &R::NAME.to_lowercase());
+    }
+    if list.cursor.is_present() && !list.sort.is_empty() {
+        return AppError::BadRequest(t!("crud.cursor_with_sort")).into_response();
+    }
+    if let Err(e) = list.fields.check(&list.include) {
+        return e.into_response();
+    }
+
+    let db = state.db();
+    match (list.fields.is_empty(), list.cursor.is_present()) {
+        (true, false) => {
+            AppResult::from(include::fetch_page(select, db, list.page, &list.include).await)
+                .into_response()
+        }
+        (true, true) => {
+            let page = include::fetch_cursor::<R, R::Key>(select, db, list.cursor, &list.include);
+            AppResult::from(page.await).into_response()
+        }
+        (false, false) => {
+            AppResult::from(fields::fetch_page(select, db, list.page, &list.fields).await)
+                .into_response()
+        }
+        (false, true) => {
+            let page = fields::fetch_cursor::<R, R::Key>(select, db, list.cursor, &list.fields);
+            AppResult::from(page.await).into_response()
+        }
+    }
+}
+
+/// 按主键取出一条记录, 支持 If-None-Match 条件请求, include 和 fields 参数
+async fn get_by_id<R: CrudResource>(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Path(key): Path<R::Key>,
+    ValidQuery(params): ValidQuery<GetParams>,
+) -> Response {
+    tracing::debug!("开始处理: 获取 {}", R::NAME);
+    if let Err(e) = params.fields.check(&params.include) {
+        return e.into_response();
+    }
+    let select = R::find_by_id(key);
+    let item = if params.fields.is_empty() {
+        include::fetch_one(select, state.db(), &params.include)
+            .await
+            .map(|item| item.map(|item| etag::respond(&headers, &item)))
+    } else {
+        fields::fetch_one(select, state.db(), &params.fields)
+            .await
+            .map(|item| item.map(|item| etag::respond(&headers, &item)))
+    };
+    match item {
+        Ok(Some(response)) => response,
+        Ok(None) => not_found::<R>().into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// 按筛选条件, 排序和分页参数查询, 和 query 接口的规则相同, 供 GraphQL 和 gRPC 使用
+pub async fn search<R: CrudResource>(
+    db: &DatabaseConnection,
+    filters: Filters,
+    sort: SortParam,
+    page: PageParam,
+) -> Result<Page<R::Model>, AppError> {
+    validate(&page)?;
+    let condition = filters.condition(R::FILTERABLE)?;
+    let select = sort.apply(R::find().filter(condition), R::SORTABLE)?;
+    Ok(Page::fetch(select, db, page).await?)
+}
+
+/// 按主键取出一条记录, 不存在时返回 404
+pub async fn find<R: CrudResource>(
+    db: &DatabaseConnection,
+    key: R::Key,
+) -> Result<R::Model, AppError> {
+    R::find_by_id(key).one(db).await?.ok_or_else(not_found::<R>)
+}
+
+/// 和 [`ValidJson`] 一样, 校验不通过时返回 422
+pub fn validate(params: &impl Validate) -> Result<(), AppError> {
+    params.validate().map_err(AppError::Validation)
+}
+
+/// 插入一条记录, 和表格导入走同一套逻辑
+async fn insert<R: CrudResource>(
+    State(state): State<ServerState>,
+    Extension(usr): Extension<UserIdent>,
+    ValidJson(params): ValidJson<R::Insert>,
+) -> AppResult<String> {
+    tracing::debug!("开始处理: 添加 {}", R::NAME);
+    throw_err!(create::<R>(state.db(), &usr, params).await);
+    AppResult::Ok(t!("crud.inserted", entity = R::NAME))
+}
+
+/// 检查权限之后在一个事务中插入一条记录, `params` 需要事先校验过
+pub async fn create<R: CrudResource>(
+    db: &DatabaseConnection,
+    usr: &UserIdent,
+    params: R::Insert,
+) -> Result<(), AppError> {
+    authorize::<R>(usr)?;
+    let txn = db.begin().await?;
+    params.import(&txn, usr).await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+/// 处理 PUT 更新请求, 以路径中的主键为准, 返回更新之后的记录
+async fn update<R: CrudResource>(
+    State(state): State<ServerState>,
+    Extension(usr): Extension<UserIdent>,
+    Path(key): Path<R::Key>,
+    if_match: IfMatch,
+    ValidJson(params): ValidJson<R::Update>,
+) -> Response {
+    tracing::debug!("开始处理: 更新 {}", R::NAME);
+    let changes = params.into_active_model();
+    match save::<R>(state.db(), &usr, key, changes, &if_match).await {
+        Ok(updated) => etag::tagged(&updated),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// 处理 PATCH 请求, 只有请求中出现了的字段会被更新, 以路径中的主键为准, 返回更新之后的记录
+async fn partial_update<R: CrudResource>(
+    State(state): State<ServerState>,
+    Extension(usr): Extension<UserIdent>,
+    Path(key): Path<R::Key>,
+    if_match: IfMatch,
+    ValidJson(params): ValidJson<R::Patch>,
+) -> Response {
+    tracing::debug!("开始处理: 部分更新 {}", R::NAME);
+    let changes = params.into_active_model();
+    match save::<R>(state.db(), &usr, key, changes, &if_match).await {
+        Ok(updated) => etag::tagged(&updated),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// 把 `changes` 中被 Set 的非主键字段合并到 `key` 所对应的记录上, 然后在一个事务中更新, 返回更新之后的记录
+///
+/// 记录在事务中被加锁, 记录在客户端读取之后被修改过, 即和 `if_match` 不符时返回 412
+pub async fn save<R: CrudResource>(
+    db: &DatabaseConnection,
+    usr: &UserIdent,
+    key: R::Key,
+    changes: R::ActiveModel,
+    if_match: &IfMatch,
+) -> Result<R::Model, AppError> {
+    authorize::<R>(usr)?;
+    let txn = db.begin().await?;
+    let Some(current) = R::find_by_id(key).lock_exclusive().one(&txn).await? else {
+        return Err(not_found::<R>());
+    };
+    if_match.check(&current)?;
+
+    let active = merge::<R>(current.clone(), &changes);
+    if !active.is_changed() {
+        return Ok(current);
+    }
+    R::before_update(&txn, &current, &active).await?;
+    let updated = active
+        .update(&txn)
+        .await
+        .map_err(|e| error::still_referenced(e, Some(R::default().table_name())))?;
+    R::after_update(&txn, &current, &updated, usr).await?;
+    events::publish(&txn, R::TOPIC, Action::Update, usr, &updated).await?;
+    txn.commit().await?;
+
+    tracing::info!("{} 更新了一条 {} 记录", usr.id, R::NAME);
+    Ok(updated)
+}
+
+/// 以 `current` 为基础, 只覆盖 `changes` 中被 Set 的非主键字段, 主键始终来自 `current`
+fn merge<R: CrudResource>(current: R::Model, changes: &R::ActiveModel) -> R::ActiveModel {
+    let mut active: R::ActiveModel = current.into();
+    for column in R::Column::iter() {
+        let is_key = R::PrimaryKey::iter().any(|key| key.into_column().as_str() == column.as_str());
+        if is_key {
+            continue;
+        }
+        if let ActiveValue::Set(value) = changes.get(column) {
+            active.set(column, value);
+        }
+    }
+    active
+}
+
+/// 按主键删除一条记录
+async fn delete<R: CrudResource>(
+    State(state): State<ServerState>,
+    Extension(usr): Extension<UserIdent>,
+    Path(key): Path<R::Key>,
+    if_match: IfMatch,
+) -> AppResult<String> {
+    tracing::debug!("开始处理: 删除 {}", R::NAME);
+    throw_err!(remove::<R>(state.db(), &usr, key, &if_match).await);
+    AppResult::Ok(t!("crud.deleted", entity = R::NAME))
+}
+
+/// 检查权限之后按主键删除一条记录, 并发出变更通知, `if_match` 的作用和 [save] 中的相同
+pub async fn remove<R: CrudResource>(
+    db: &DatabaseConnection,
+    usr: &UserIdent,
+    key: R::Key,
+    if_match: &IfMatch,
+) -> Result<(), AppError> {
+    authorize::<R>(usr)?;
+    let txn = db.begin().await?;
+    let Some(item) = R::find_by_id(key).lock_exclusive().one(&txn).await? else {
+        return Err(not_found::<R>());
+    };
+    if_match.check(&item)?;
+    R::before_delete(&txn, &item, usr).await?;
+    item.clone()
+        .delete(&txn)
+        .await
+        .map_err(|e| error::still_referenced(e, None))?;
+    events::publish(&txn, R::TOPIC, Action::Delete, usr, &item).await?;
+    txn.commit().await?;
+    tracing::info!("{} 删除了一条 {} 记录", usr.id, R::NAME);
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use crate::error::AppError;
+use crate::t;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// 根据响应体计算强 ETag, 内容相同则 ETag 相同
+pub fn etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+    format!("\"{hex}\"")
+}
+
+/// 判断 `etag` 是否出现在 If-None-Match 所列出的 ETag 中, 按 RFC 9110 使用弱比较, 即忽略 `W/` 前缀
+fn matches_weak(header: &HeaderValue, etag: &str) -> bool {
+    tags(header).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// 判断 `etag` 是否出现在 If-Match 所列出的 ETag 中, 按 RFC 9110 使用强比较, 弱 ETag 不会匹配任何记录
+fn matches_strong(header: &HeaderValue, etag: &str) -> bool {
+    tags(header).any(|tag| tag == "*" || (!tag.starts_with("W/") && tag == etag))
+}
+
+fn tags(header: &HeaderValue) -> impl Iterator<Item = &str> {
+    header
+        .to_str()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+}
+
+/// `value` 的 ETag, 和把它作为响应体返回时的 ETag 相同, 供 GraphQL 和 gRPC 返回记录的版本
+pub fn of<T: Serialize>(value: &T) -> Result<String, AppError> {
+    serialize(value).map(|(_, etag)| etag)
+}
+
+/// 将 `value` 序列化为 JSON, 返回响应体和它的 ETag
+fn serialize<T: Serialize>(value: &T) -> Result<(Vec<u8>, String), AppError> {
+    let body = serde_json::to_vec(value)
+        .map_err(|e| AppError::Internal(t!("etag.serialize", error = e)))?;
+    let etag = etag(&body);
+    Ok((body, etag))
+}
+
+fn json_with_etag(body: Vec<u8>, etag: String) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::ETAG, etag),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+/// 将 `value` 序列化为 JSON 并带上 ETag 返回, 如果请求头 If-None-Match 中的 ETag 和它相同, 则返回 304
+pub fn respond<T: Serialize>(headers: &HeaderMap, value: &T) -> Response {
+    let (body, etag) = match serialize(value) {
+        Ok(serialized) => serialized,
+        Err(e) => return e.into_response(),
+    };
+
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .is_some_and(|header| matches_weak(header, &etag))
+    {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+    json_with_etag(body, etag)
+}
+
+/// 将 `value` 序列化为 JSON 并带上 ETag 返回, 更新之后返回新的记录时使用, 客户端可以直接用这个 ETag 进行下一次修改
+pub fn tagged<T: Serialize>(value: &T) -> Response {
+    match serialize(value) {
+        Ok((body, etag)) => json_with_etag(body, etag),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// 请求头 If-Match 中列出的 ETag, 更新和删除记录时必须带上, 缺少时返回 428
+///
+/// 一条记录的 ETag 就是按主键获取它 (不带 include 和 fields) 时响应中的 ETag, 记录的任何字段发生变化 ETag 都会改变
+pub struct IfMatch(HeaderValue);
+
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.headers.get(header::IF_MATCH) {
+            Some(header) => Ok(IfMatch(header.clone())),
+            None => Err(AppError::PreconditionRequired(t!("etag.if_match_required"))),
+        }
+    }
+}
+
+impl IfMatch {
+    /// 由 GraphQL 和 gRPC 请求中的 `etag` 参数构造, 为空时和缺少 If-Match 请求头一样返回 428
+    pub fn from_etag(etag: &str) -> Result<Self, AppError> {
+        if etag.trim().is_empty() {
+            return Err(AppError::PreconditionRequired(t!("etag.etag_required")));
+        }
+        HeaderValue::from_str(etag)
+            .map(IfMatch)
+            .map_err(|e| AppError::BadRequest(t!("etag.bad_etag", error = e)))
+    }
+
+    /// 检查 `current` 是否仍是客户端读取时的版本, 在读取之后被修改过时返回 412
+    pub fn check<T: Serialize>(&self, current: &T) -> Result<(), AppError> {
+        let (_, etag) = serialize(current)?;
+        if matches_strong(&self.0, &etag) {
+            Ok(())
+        } else {
+            Err(AppError::PreconditionFailed(t!(
+                "etag.modified",
+                etag = etag
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_if_none_match() {
+        let tag = etag(b"{}");
+        assert_eq!(tag, etag(b"{}"));
+        assert_ne!(tag, etag(b"[]"));
+
+        let header = HeaderValue::from_str(&format!("\"other\", W/{tag}")).unwrap();
+        assert!(matches_weak(&header, &tag));
+        assert!(matches_weak(&HeaderValue::from_static("*"), &tag));
+        assert!(!matches_weak(&HeaderValue::from_static("\"other\""), &tag));
+    }
+
+    #[test]
+    fn test_if_match() {
+        let (_, tag) = serialize(&[1, 2]).unwrap();
+        let if_match = IfMatch(HeaderValue::from_str(&tag).unwrap());
+        assert!(if_match.check(&[1, 2]).is_ok());
+
+        // If-Match 使用强比较, 弱 ETag 即使值相同也不匹配
+        let weak = IfMatch(HeaderValue::from_str(&format!("W/{tag}")).unwrap());
+        assert!(weak.check(&[1, 2]).is_err());
+
+        let e = if_match.check(&[1, 3]).unwrap_err();
+        assert_eq!(e.status_code(), StatusCode::PRECONDITION_FAILED);
+        assert!(
+            IfMatch(HeaderValue::from_static("*"))
+                .check(&[1, 3])
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_from_etag() {
+        let tag = of(&[1, 2]).unwrap();
+        assert!(IfMatch::from_etag(&tag).unwrap().check(&[1, 2]).is_ok());
+        let e = IfMatch::from_etag(&tag)
+            .unwrap()
+            .check(&[1, 3])
+            .unwrap_err();
+        assert_eq!(e.status_code(), StatusCode::PRECONDITION_FAILED);
+
+        let e = IfMatch::from_etag(" ").err().unwrap();
+        assert_eq!(e.status_code(), StatusCode::PRECONDITION_REQUIRED);
+        let e = IfMatch::from_etag("\"a\nb\"").err().unwrap();
+        assert_eq!(e.status_code(), StatusCode::BAD_REQUEST);
+    }
+}
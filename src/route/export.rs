@@ -0,0 +1,243 @@
+use axum::body::Body;
+use axum::http::{HeaderMap, header};
+use axum::response::{IntoResponse, Response};
+use rust_xlsxwriter::Workbook;
+use schemars::JsonSchema;
+use sea_orm::{DatabaseConnection, EntityTrait, Select};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::PathBuf;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::ReceiverStream;
+
+/// 每次编码并发送给客户端的行数
+const ROWS_PER_CHUNK: usize = 500;
+/// 发送队列中最多积压的块数, 客户端读得慢时数据库的读取也会随之暂停
+const CHANNEL_CAPACITY: usize = 16;
+/// Excel 需要 BOM 才能正确识别 UTF-8 编码的 CSV
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// 导出查询结果时支持的格式
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Xlsx,
+    Ndjson,
+}
+
+impl ExportFormat {
+    /// `format` 参数优先于 Accept 请求头, 两者都没有指定导出格式时返回 None, 即返回分页的 JSON
+    pub fn negotiate(param: Option<ExportFormat>, headers: &HeaderMap) -> Option<ExportFormat> {
+        param.or_else(|| {
+            headers
+                .get(header::ACCEPT)
+                .and_then(|accept| accept.to_str().ok())
+                .and_then(|accept| {
+                    accept
+                        .split(',')
+                        .map(|media| media.split(';').next().unwrap_or_default().trim())
+                        .find_map(ExportFormat::from_mime)
+                })
+        })
+    }
+
+    fn from_mime(mime: &str) -> Option<ExportFormat> {
+        match mime {
+            "text/csv" => Some(ExportFormat::Csv),
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => {
+                Some(ExportFormat::Xlsx)
+            }
+            "application/x-ndjson" | "application/jsonl" => Some(ExportFormat::Ndjson),
+            _ => None,
+        }
+    }
+
+    fn mime(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Xlsx => "xlsx",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+type Sender = mpsc::Sender<io::Result<Vec<u8>>>;
+
+/// 将 `select` 的全部结果 (不分页) 以 `format` 格式流式地返回给客户端, `name` 为下载时的文件名
+///
+/// 数据库的读取和编码在另一个任务中进行, 通过一个有界队列交给响应体, 所以内存中最多只会有几块数据
+pub fn export<E>(
+    select: Select<E>,
+    db: &DatabaseConnection,
+    format: ExportFormat,
+    name: &str,
+) -> Response
+where
+    E: EntityTrait,
+    E::Model: Serialize + DeserializeOwned + Send + Sync,
+{
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let db = db.clone();
+    tokio::spawn(async move {
+        let result = match format {
+            ExportFormat::Csv => write_csv(select, &db, &tx).await,
+            ExportFormat::Ndjson => write_ndjson(select, &db, &tx).await,
+            ExportFormat::Xlsx => write_xlsx(select, &db, &tx).await,
+        };
+        if let Err(e) = result {
+            tracing::error!("导出数据时出错: {e}");
+            // 响应头已经发出去了, 只能让响应体以错误结束, 客户端会看到连接中断
+            let _ = tx.send(Err(io::Error::other(e.to_string()))).await;
+        }
+    });
+
+    tracing::info!("开始导出 {name}, 格式: {format:?}");
+    (
+        [
+            (header::CONTENT_TYPE, format.mime().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}.{}\"", format.extension()),
+            ),
+        ],
+        Body::from_stream(ReceiverStream::new(rx)),
+    )
+        .into_response()
+}
+
+async fn send(tx: &Sender, chunk: Vec<u8>) -> anyhow::Result<()> {
+    tx.send(Ok(chunk))
+        .await
+        .map_err(|_| anyhow::anyhow!("客户端已断开连接"))
+}
+
+/// 逐块读取 `select` 的结果, 用 `encode` 编码后发送, `encode` 的第二个参数表示是否是第一块
+async fn stream_rows<E>(
+    select: Select<E>,
+    db: &DatabaseConnection,
+    tx: &Sender,
+    mut encode: impl FnMut(&[E::Model], bool) -> anyhow::Result<Vec<u8>>,
+) -> anyhow::Result<()>
+where
+    E: EntityTrait,
+    E::Model: Send + Sync,
+{
+    let mut rows = select.stream(db).await?;
+    let mut chunk = Vec::with_capacity(ROWS_PER_CHUNK);
+    let mut first = true;
+
+    while let Some(row) = rows.next().await {
+        chunk.push(row?);
+        if chunk.len() == ROWS_PER_CHUNK {
+            send(tx, encode(&chunk, first)?).await?;
+            chunk.clear();
+            first = false;
+        }
+    }
+    if !chunk.is_empty() {
+        send(tx, encode(&chunk, first)?).await?;
+    }
+    Ok(())
+}
+
+async fn write_csv<E>(select: Select<E>, db: &DatabaseConnection, tx: &Sender) -> anyhow::Result<()>
+where
+    E: EntityTrait,
+    E::Model: Serialize + Send + Sync,
+{
+    send(tx, UTF8_BOM.to_vec()).await?;
+    stream_rows(select, db, tx, |rows, first| {
+        // 只有第一块需要表头
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(first)
+            .from_writer(Vec::new());
+        for row in rows {
+            writer.serialize(row)?;
+        }
+        Ok(writer.into_inner()?)
+    })
+    .await
+}
+
+async fn write_ndjson<E>(
+    select: Select<E>,
+    db: &DatabaseConnection,
+    tx: &Sender,
+) -> anyhow::Result<()>
+where
+    E: EntityTrait,
+    E::Model: Serialize + Send + Sync,
+{
+    stream_rows(select, db, tx, |rows, _| {
+        let mut buf = Vec::new();
+        for row in rows {
+            serde_json::to_writer(&mut buf, row)?;
+            buf.push(b'\n');
+        }
+        Ok(buf)
+    })
+    .await
+}
+
+/// 导出时使用的临时文件, 离开作用域时被删除
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            tracing::warn!("无法删除临时文件 {}: {e}", self.0.display());
+        }
+    }
+}
+
+/// XLSX 本质上是一个 zip 压缩包, 没法边生成边发送, 所以使用 constant memory 模式把数据写到临时文件中,
+/// 生成完毕之后再把文件分块发送给客户端
+async fn write_xlsx<E>(
+    select: Select<E>,
+    db: &DatabaseConnection,
+    tx: &Sender,
+) -> anyhow::Result<()>
+where
+    E: EntityTrait,
+    E::Model: Serialize + DeserializeOwned + Send + Sync,
+{
+    let mut workbook = Workbook::new();
+    let worksheet = workbook.add_worksheet_with_constant_memory();
+    worksheet.deserialize_headers::<E::Model>(0, 0)?;
+
+    let mut rows = select.stream(db).await?;
+    while let Some(row) = rows.next().await {
+        worksheet.serialize(&row?)?;
+    }
+    drop(rows);
+
+    let file =
+        TempFile(std::env::temp_dir().join(format!("web-start-{}.xlsx", uuid::Uuid::new_v4())));
+    let path = file.0.clone();
+    tokio::task::spawn_blocking(move || workbook.save(path)).await??;
+
+    let mut reader = tokio::fs::File::open(&file.0).await?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let len = reader.read(&mut buf).await?;
+        if len == 0 {
+            break;
+        }
+        send(tx, buf[..len].to_vec()).await?;
+    }
+    Ok(())
+}
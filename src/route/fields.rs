@@ -0,0 +1,130 @@
+use crate::comma_list;
+use crate::error::AppError;
+use crate::route::include::IncludeParam;
+use crate::route::page::{self, CursorPage, CursorParam, Page, PageParam};
+use crate::t;
+use sea_orm::{
+    DatabaseConnection, EntityTrait, IdenStatic, Iterable, PrimaryKeyToColumn, PrimaryKeyTrait,
+    QuerySelect, Select,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// `fields` 参数, 形如 `fields=id,name`, 指定结果中只需要哪些字段
+///
+/// 只有这些字段 (以及主键) 会从数据库中查询出来, 而不是查询出整行之后再去掉
+#[derive(Default, Debug, PartialEq)]
+pub struct FieldsParam(Vec<String>);
+
+comma_list!(FieldsParam, "id,name");
+
+impl FieldsParam {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 只查询部分字段时无法加载关联数据, 因此不能和 include 一起使用
+    pub fn check(&self, include: &IncludeParam) -> Result<(), AppError> {
+        if !self.is_empty() && !include.is_empty() {
+            return Err(AppError::BadRequest(t!("fields.with_include")));
+        }
+        Ok(())
+    }
+
+    /// 校验字段名并返回要查询的列, 主键总会被包含在内, 列的顺序和实体中的一致
+    fn columns<E: EntityTrait>(&self) -> Result<Vec<E::Column>, AppError> {
+        if let Some(field) = self
+            .0
+            .iter()
+            .find(|field| !E::Column::iter().any(|c| c.as_str() == field.as_str()))
+        {
+            let fields: Vec<_> = E::Column::iter().map(|c| c.as_str().to_string()).collect();
+            return Err(AppError::BadRequest(t!(
+                "fields.unknown",
+                field = field,
+                fields = fields.join(", "),
+            )));
+        }
+
+        Ok(E::Column::iter()
+            .filter(|column| {
+                E::PrimaryKey::iter().any(|key| key.into_column().as_str() == column.as_str())
+                    || self.0.iter().any(|field| field == column.as_str())
+            })
+            .collect())
+    }
+
+    /// 让 `select` 只查询选中的列
+    fn apply<E: EntityTrait>(&self, select: Select<E>) -> Result<Select<E>, AppError> {
+        Ok(select.select_only().columns(self.columns::<E>()?))
+    }
+}
+
+/// 和 [Page::fetch] 一样取出一页数据, 但只查询 `fields` 中的列, 每一条记录以 JSON 对象返回
+pub async fn fetch_page<E: EntityTrait>(
+    select: Select<E>,
+    db: &DatabaseConnection,
+    param: PageParam,
+    fields: &FieldsParam,
+) -> Result<Page<Value>, AppError> {
+    let select = fields.apply(select)?.into_json();
+    Ok(Page::fetch(select, db, param).await?)
+}
+
+/// 和 [CursorPage::fetch] 一样按游标取出一段数据, 但只查询 `fields` 中的列
+pub async fn fetch_cursor<E, K>(
+    select: Select<E>,
+    db: &DatabaseConnection,
+    param: CursorParam,
+    fields: &FieldsParam,
+) -> Result<CursorPage<Value>, AppError>
+where
+    E: EntityTrait<Model: Sync>,
+    K: DeserializeOwned + Into<<E::PrimaryKey as PrimaryKeyTrait>::ValueType>,
+{
+    let cursor = fields
+        .apply(select)?
+        .cursor_by(page::primary_key::<E>())
+        .into_json();
+    CursorPage::fetch::<E, K, _>(cursor, db, param).await
+}
+
+/// 取出 `select` 的第一条记录, 只查询 `fields` 中的列
+pub async fn fetch_one<E: EntityTrait>(
+    select: Select<E>,
+    db: &DatabaseConnection,
+    fields: &FieldsParam,
+) -> Result<Option<Value>, AppError> {
+    Ok(fields.apply(select)?.into_json().one(db).await?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entity::prelude::{Score, Student};
+
+    fn names<E: EntityTrait>(fields: &str) -> Result<Vec<String>, AppError> {
+        let fields: FieldsParam = serde_json::from_value(fields.into()).unwrap();
+        Ok(fields
+            .columns::<E>()?
+            .into_iter()
+            .map(|column| column.as_str().to_string())
+            .collect())
+    }
+
+    #[test]
+    fn test_fields_param() {
+        assert_eq!(
+            names::<Student>("email, name,").unwrap(),
+            ["id", "name", "email"]
+        );
+        assert_eq!(
+            names::<Score>("score").unwrap(),
+            ["stu_id", "course_id", "score"]
+        );
+        assert!(matches!(
+            names::<Student>("password"),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::error::AppError;
+use crate::t;
+use axum::extract::{FromRequestParts, Query};
+use axum::http::request::Parts;
+use sea_orm::prelude::{Date, DateTime};
+use sea_orm::sea_query::SimpleExpr;
+use sea_orm::{ColumnTrait, ColumnType, Condition, Value};
+use std::str::FromStr;
+
+/// 从查询字符串中收集的 `filter[字段][操作]=值` 形式的筛选条件, 多个条件之间是"且"的关系
+///
+/// 省略操作时 (`filter[字段]=值`) 视为 `eq`, 其他查询参数会被忽略
+pub struct Filters(Vec<Filter>);
+
+struct Filter {
+    field: String,
+    op: String,
+    value: String,
+}
+
+/// 支持的筛选操作
+#[derive(Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Contains,
+    StartsWith,
+    In,
+    Null,
+}
+
+impl Op {
+    const NAMES: &'static str = "eq, ne, gt, gte, lt, lte, contains, starts_with, in, null";
+
+    fn parse(op: &str) -> Result<Self, AppError> {
+        Ok(match op {
+            "eq" => Op::Eq,
+            "ne" => Op::Ne,
+            "gt" => Op::Gt,
+            "gte" => Op::Gte,
+            "lt" => Op::Lt,
+            "lte" => Op::Lte,
+            "contains" => Op::Contains,
+            "starts_with" => Op::StartsWith,
+            "in" => Op::In,
+            "null" => Op::Null,
+            _ => {
+                return Err(AppError::BadRequest(t!(
+                    "filter.unknown_op",
+                    op = op,
+                    ops = Op::NAMES,
+                )));
+            }
+        })
+    }
+}
+
+impl Filters {
+    /// 支持的筛选操作, 用于文档
+    pub const OPS: &'static str = Op::NAMES;
+
+    /// 由 (字段, 操作, 值) 直接构造筛选条件, 供不经过查询字符串的调用方 (如 GraphQL) 使用
+    pub fn new(filters: impl IntoIterator<Item = (String, String, String)>) -> Self {
+        Filters(
+            filters
+                .into_iter()
+                .map(|(field, op, value)| Filter { field, op, value })
+                .collect(),
+        )
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Filters {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(pairs) = Query::<Vec<(String, String)>>::from_request_parts(parts, state).await?;
+        let mut filters = Vec::new();
+        for (key, value) in pairs {
+            if let Some(filter) = Filter::parse(&key, value)? {
+                filters.push(filter);
+            }
+        }
+        Ok(Filters(filters))
+    }
+}
+
+impl Filter {
+    /// 解析 `filter[字段]` 或 `filter[字段][操作]`, 不以 `filter[` 开头的参数返回 None
+    fn parse(key: &str, value: String) -> Result<Option<Self>, AppError> {
+        let Some(rest) = key.strip_prefix("filter[") else {
+            return Ok(None);
+        };
+        let malformed = || AppError::BadRequest(t!("filter.malformed", key = key));
+
+        let (field, rest) = rest.split_once(']').ok_or_else(malformed)?;
+        let op = match rest {
+            "" => "eq",
+            _ => rest
+                .strip_prefix('[')
+                .and_then(|op| op.strip_suffix(']'))
+                .ok_or_else(malformed)?,
+        };
+        if field.is_empty() || op.is_empty() {
+            return Err(malformed());
+        }
+        Ok(Some(Filter {
+            field: field.to_string(),
+            op: op.to_string(),
+            value,
+        }))
+    }
+}
+
+impl Filters {
+    /// 把筛选条件转换为查询条件, 只允许按 `allowed` 中的列筛选
+    pub fn condition<C: ColumnTrait>(&self, allowed: &[C]) -> Result<Condition, AppError> {
+        let mut condition = Condition::all();
+        for filter in &self.0 {
+            let Some(column) = allowed.iter().find(|c| c.as_str() == filter.field) else {
+                let fields: Vec<_> = allowed.iter().map(|c| c.as_str()).collect();
+                return Err(AppError::BadRequest(t!(
+                    "filter.unknown_field",
+                    field = filter.field,
+                    fields = fields.join(", "),
+                )));
+            };
+            let op = Op::parse(&filter.op)?;
+            condition = condition.add(expr(*column, op, &filter.field, &filter.value)?);
+        }
+        Ok(condition)
+    }
+}
+
+fn expr<C: ColumnTrait>(
+    column: C,
+    op: Op,
+    field: &str,
+    value: &str,
+) -> Result<SimpleExpr, AppError> {
+    let column_type = column.def().get_column_type().clone();
+    let is_text = matches!(
+        column_type,
+        ColumnType::Char(_) | ColumnType::String(_) | ColumnType::Text
+    );
+    let parse = |value: &str| parse_value(&column_type, field, value);
+
+    Ok(match op {
+        Op::Eq => column.eq(parse(value)?),
+        Op::Ne => column.ne(parse(value)?),
+        Op::Gt => column.gt(parse(value)?),
+        Op::Gte => column.gte(parse(value)?),
+        Op::Lt => column.lt(parse(value)?),
+        Op::Lte => column.lte(parse(value)?),
+        Op::Contains | Op::StartsWith if !is_text => {
+            return Err(AppError::BadRequest(t!("filter.not_text", field = field)));
+        }
+        Op::Contains => column.contains(value),
+        Op::StartsWith => column.starts_with(value),
+        Op::In => column.is_in(
+            value
+                .split(',')
+                .map(|item| parse(item.trim()))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        Op::Null => match value {
+            "true" => column.is_null(),
+            "false" => column.is_not_null(),
+            _ => {
+                return Err(AppError::BadRequest(t!("filter.null_value", field = field)));
+            }
+        },
+    })
+}
+
+/// 按列的类型解析筛选的值
+fn parse_value(column_type: &ColumnType, field: &str, value: &str) -> Result<Value, AppError> {
+    fn typed<T: FromStr + Into<Value>>(field: &str, value: &str) -> Result<Value, AppError> {
+        value
+            .parse::<T>()
+            .map(Into::into)
+            .map_err(|_| AppError::BadRequest(t!("filter.bad_value", value = value, field = field)))
+    }
+
+    match column_type {
+        ColumnType::Char(_) | ColumnType::String(_) | ColumnType::Text => {
+            Ok(value.to_string().into())
+        }
+        ColumnType::SmallInteger => typed::<i16>(field, value),
+        ColumnType::Integer => typed::<i32>(field, value),
+        ColumnType::BigInteger => typed::<i64>(field, value),
+        ColumnType::Boolean => typed::<bool>(field, value),
+        ColumnType::Date => typed::<Date>(field, value),
+        ColumnType::DateTime | ColumnType::Timestamp => typed::<DateTime>(field, value),
+        _ => Err(AppError::BadRequest(t!(
+            "filter.unsupported",
+            field = field
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entity::prelude::Student;
+    use crate::entity::student::Column;
+    use sea_orm::{DbBackend, EntityTrait, QueryFilter, QueryTrait};
+
+    fn filters(pairs: &[(&str, &str)]) -> Result<Filters, AppError> {
+        let mut filters = Vec::new();
+        for (key, value) in pairs {
+            filters.extend(Filter::parse(key, value.to_string())?);
+        }
+        Ok(Filters(filters))
+    }
+
+    #[test]
+    fn test_filter() {
+        let allowed = [Column::Name, Column::Age, Column::DepartmentId];
+        let sql = |pairs: &[(&str, &str)]| {
+            let condition = filters(pairs)?.condition(&allowed)?;
+            Ok::<_, AppError>(
+                Student::find()
+                    .filter(condition)
+                    .build(DbBackend::Postgres)
+                    .to_string(),
+            )
+        };
+
+        let built = sql(&[
+            ("filter[age][gte]", "18"),
+            ("filter[name][contains]", "李"),
+            ("filter[department_id][in]", "01,02"),
+            ("size", "10"),
+        ])
+        .unwrap();
+        assert!(built.contains(r#""student"."age" >= 18"#));
+        assert!(built.contains(r#""student"."name" LIKE '%李%'"#));
+        assert!(built.contains(r#""student"."department_id" IN ('01', '02')"#));
+        assert!(
+            sql(&[("filter[age]", "18")])
+                .unwrap()
+                .contains(r#""age" = 18"#)
+        );
+
+        assert!(matches!(
+            sql(&[("filter[email]", "a")]),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            sql(&[("filter[age][like]", "1")]),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            sql(&[("filter[age]", "x")]),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            sql(&[("filter[age][contains]", "1")]),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            sql(&[("filter[age", "1")]),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
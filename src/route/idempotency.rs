@@ -0,0 +1,261 @@
+use crate::app_config;
+use crate::entity::idempotency_key::{ActiveModel, Column};
+use crate::entity::prelude::IdempotencyKey;
+use crate::error::AppError;
+use crate::route::middleware;
+use crate::server::{BODY_LIMIT, REQUEST_TIMEOUT, ServerState};
+use crate::t;
+use axum::body::{Body, to_bytes};
+use axum::extract::{Request, State};
+use axum::http::{HeaderName, HeaderValue, Method, StatusCode, Uri, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sea_orm::sea_query::OnConflict;
+use sea_orm::{
+    ActiveValue, ColumnTrait, Condition, DatabaseConnection, DbErr, EntityTrait, QueryFilter,
+    TryInsertResult,
+};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+/// 客户端为一次操作生成的唯一标识, 重试时带上同一个值
+pub static IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// 重放保存的响应时带上这个响应头
+static REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// 处理带有 `Idempotency-Key` 请求头的 POST 请求
+///
+/// 第一次请求正常执行, 响应会被保存下来, 之后带有同一个 key 的重试直接返回保存的响应, 不会再执行一次.
+/// key 按用户区分, 同一个 key 被用于不同的请求 (方法, 路径或请求体不同) 时返回 422,
+/// 第一次请求还没有完成时返回 409. 服务器出错 (5xx) 时不会保存响应, 客户端可以用同一个 key 重试.
+/// 第一次请求因为进程崩溃或连接断开而没有完成时, 超过请求的超时时间后就可以用同一个 key 重试
+pub async fn idempotency(
+    State(state): State<ServerState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if request.method() != Method::POST {
+        return next.run(request).await;
+    }
+    let Some(key) = request.headers().get(&IDEMPOTENCY_KEY).cloned() else {
+        return next.run(request).await;
+    };
+    // 未登录的请求交给后面的认证层拒绝, 不需要登录的接口 (如登录) 也不需要幂等
+    let auth_header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    let Ok(usr) = middleware::authenticate(auth_header) else {
+        return next.run(request).await;
+    };
+
+    match handle(state.db(), &usr.id, &key, request, next).await {
+        Ok(response) => response,
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn handle(
+    db: &DatabaseConnection,
+    user_id: &str,
+    key: &HeaderValue,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let key = key
+        .to_str()
+        .ok()
+        .filter(|key| (1..=255).contains(&key.len()))
+        .ok_or_else(|| AppError::BadRequest(t!("idempotency.bad_key")))?;
+
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, BODY_LIMIT)
+        .await
+        .map_err(|e| AppError::BadRequest(t!("idempotency.read_request", error = e)))?;
+    let hash = request_hash(&parts.method, &parts.uri, &body);
+
+    if !claim(db, user_id, key, &hash).await? {
+        let Some(saved) = IdempotencyKey::find_by_id((user_id.to_string(), key.to_string()))
+            .one(db)
+            .await?
+        else {
+            return Err(in_progress());
+        };
+        if saved.request_hash != hash {
+            return Err(AppError::UnprocessableEntity(t!("idempotency.reused")));
+        }
+        let Some(status) = saved.status else {
+            return Err(in_progress());
+        };
+        tracing::info!("{user_id} 重试了 Idempotency-Key 为 {key} 的请求, 返回保存的响应");
+        return Ok(replay(status, saved.content_type, saved.body));
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            release(db, user_id, key).await;
+            return Err(AppError::Internal(t!(
+                "idempotency.read_response",
+                error = e
+            )));
+        }
+    };
+
+    if parts.status.is_server_error() {
+        release(db, user_id, key).await;
+    } else {
+        let saved = ActiveModel {
+            user_id: ActiveValue::Unchanged(user_id.to_string()),
+            key: ActiveValue::Unchanged(key.to_string()),
+            status: ActiveValue::Set(Some(parts.status.as_u16().into())),
+            content_type: ActiveValue::Set(
+                parts
+                    .headers
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string),
+            ),
+            body: ActiveValue::Set(Some(body.to_vec())),
+            ..Default::default()
+        };
+        if let Err(e) = IdempotencyKey::update(saved).exec(db).await {
+            tracing::error!("无法保存 Idempotency-Key 为 {key} 的响应, 详细信息: {e}");
+        }
+    }
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+fn in_progress() -> AppError {
+    AppError::Conflict(t!("idempotency.in_progress"))
+}
+
+/// 由方法, 路径 (包括查询参数) 和请求体计算出的摘要, 用于判断重试的是不是同一个请求
+fn request_hash(method: &Method, uri: &Uri, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str());
+    hasher.update(b" ");
+    hasher.update(uri.to_string());
+    hasher.update(b"\n");
+    hasher.update(body);
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// 尝试占用这个 key, 返回 false 表示这个 key 已经被占用了
+///
+/// 已经过期的记录视为不存在. 还没有响应但占用时间超过了请求超时时间的记录也视为不存在,
+/// 这样的请求一定已经被取消了 (如进程崩溃或者连接断开), 不会再保存响应
+async fn claim(
+    db: &DatabaseConnection,
+    user_id: &str,
+    key: &str,
+    hash: &str,
+) -> Result<bool, DbErr> {
+    let now = chrono::Local::now().naive_local();
+    IdempotencyKey::delete_many()
+        .filter(Column::UserId.eq(user_id))
+        .filter(Column::Key.eq(key))
+        .filter(
+            Condition::any()
+                .add(Column::CreatedAt.lt(expired_before()))
+                .add(
+                    Condition::all()
+                        .add(Column::Status.is_null())
+                        .add(Column::CreatedAt.lt(now - REQUEST_TIMEOUT)),
+                ),
+        )
+        .exec(db)
+        .await?;
+
+    let claimed = ActiveModel {
+        user_id: ActiveValue::Set(user_id.to_string()),
+        key: ActiveValue::Set(key.to_string()),
+        request_hash: ActiveValue::Set(hash.to_string()),
+        created_at: ActiveValue::Set(now),
+        ..Default::default()
+    };
+    let result = IdempotencyKey::insert(claimed)
+        .on_conflict(
+            OnConflict::columns([Column::UserId, Column::Key])
+                .do_nothing()
+                .to_owned(),
+        )
+        .do_nothing()
+        .exec_without_returning(db)
+        .await?;
+    Ok(matches!(result, TryInsertResult::Inserted(1)))
+}
+
+/// 放弃这个 key, 之后的重试会重新执行请求
+async fn release(db: &DatabaseConnection, user_id: &str, key: &str) {
+    let result = IdempotencyKey::delete_by_id((user_id.to_string(), key.to_string()))
+        .exec(db)
+        .await;
+    if let Err(e) = result {
+        tracing::error!("无法删除 Idempotency-Key {key}, 详细信息: {e}");
+    }
+}
+
+fn replay(status: i32, content_type: Option<String>, body: Option<Vec<u8>>) -> Response {
+    let status = u16::try_from(status)
+        .ok()
+        .and_then(|status| StatusCode::from_u16(status).ok())
+        .unwrap_or(StatusCode::OK);
+    let mut response = (status, body.unwrap_or_default()).into_response();
+    let headers = response.headers_mut();
+    if let Some(content_type) = content_type.and_then(|v| HeaderValue::from_str(&v).ok()) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    headers.insert(REPLAYED.clone(), HeaderValue::from_static("true"));
+    response
+}
+
+fn expired_before() -> chrono::NaiveDateTime {
+    let ttl = app_config::get_server().idempotency_ttl();
+    chrono::Local::now().naive_local() - ttl
+}
+
+/// 每小时清理一次过期的 Idempotency-Key
+pub async fn purge(db: DatabaseConnection) {
+    loop {
+        let result = IdempotencyKey::delete_many()
+            .filter(Column::CreatedAt.lt(expired_before()))
+            .exec(&db)
+            .await;
+        match result {
+            Ok(result) if result.rows_affected > 0 => {
+                tracing::info!("清理了 {} 个过期的 Idempotency-Key", result.rows_affected);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!("清理过期的 Idempotency-Key 时出错, 详细信息: {e}"),
+        }
+        tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_request_hash() {
+        let uri: Uri = "/api/score/insert".parse().unwrap();
+        let hash = request_hash(&Method::POST, &uri, b"{\"score\": 90}");
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, request_hash(&Method::POST, &uri, b"{\"score\": 90}"));
+        assert_ne!(hash, request_hash(&Method::POST, &uri, b"{\"score\": 91}"));
+
+        let other: Uri = "/api/score/bulk".parse().unwrap();
+        assert_ne!(
+            hash,
+            request_hash(&Method::POST, &other, b"{\"score\": 90}")
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use crate::comma_list;
+use crate::entity::prelude::{Course, Department, Score, ScoreHistory, Student, Term};
+use crate::entity::{course, department, score, score_history, student, term};
+use crate::error::AppError;
+use crate::route::page::{self, CursorPage, CursorParam, Page, PageParam};
+use crate::t;
+use schemars::JsonSchema;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, FromQueryResult, LoaderTrait,
+    PrimaryKeyTrait, QueryFilter, Related, Select,
+};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// `include` 参数, 形如 `include=department,scores`, 指定要嵌入到结果中的关联数据
+#[derive(Default, Debug, PartialEq)]
+pub struct IncludeParam(Vec<String>);
+
+comma_list!(IncludeParam, "department,scores");
+
+impl IncludeParam {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 检查每一个关联名是否都是 `E` 所支持的
+    fn check<E: Include>(&self) -> Result<(), AppError> {
+        match self
+            .0
+            .iter()
+            .find(|name| !E::INCLUDES.contains(&name.as_str()))
+        {
+            Some(name) => Err(AppError::BadRequest(t!(
+                "include.unknown",
+                name = name,
+                includes = E::INCLUDES.join(", "),
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 嵌入了关联数据的一条记录, 没有指定 include 时和原来的记录完全相同
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "Embedded_{M}")]
+pub struct Embedded<M: Serialize> {
+    #[serde(flatten)]
+    item: M,
+    #[serde(flatten)]
+    included: Map<String, Value>,
+}
+
+/// 可以通过 `include` 参数嵌入关联数据的实体
+///
+/// 每一种关联只会多执行一次查询, 而不是每一行都查询一次
+pub trait Include: EntityTrait<Model: Serialize + Send + Sync> {
+    /// 可以嵌入的关联名
+    const INCLUDES: &'static [&'static str];
+
+    /// 关联数据在结果中的字段名, 默认和关联名相同, 只有和已有的字段重名时才需要改
+    fn key(relation: &str) -> String {
+        relation.to_string()
+    }
+
+    /// 按 `models` 的顺序取出每一行 `relation` 所对应的关联数据
+    ///
+    /// 显式地要求 Send, 否则泛型的 handler 无法证明自己的 Future 是 Send 的
+    fn load(
+        relation: &str,
+        models: &[Self::Model],
+        db: &DatabaseConnection,
+    ) -> impl Future<Output = Result<Vec<Value>, DbErr>> + Send;
+}
+
+/// 为 `items` 嵌入 `include` 中指定的关联数据
+pub async fn embed<E: Include>(
+    items: Vec<E::Model>,
+    include: &IncludeParam,
+    db: &DatabaseConnection,
+) -> Result<Vec<Embedded<E::Model>>, AppError> {
+    include.check::<E>()?;
+
+    let mut included = vec![Map::new(); items.len()];
+    for relation in &include.0 {
+        let values = E::load(relation, &items, db).await?;
+        for (map, value) in included.iter_mut().zip(values) {
+            map.insert(E::key(relation), value);
+        }
+    }
+
+    Ok(items
+        .into_iter()
+        .zip(included)
+        .map(|(item, included)| Embedded { item, included })
+        .collect())
+}
+
+/// 和 [Page::fetch] 一样取出一页数据, 再嵌入 `include` 中指定的关联数据
+pub async fn fetch_page<E>(
+    select: Select<E>,
+    db: &DatabaseConnection,
+    param: PageParam,
+    include: &IncludeParam,
+) -> Result<Page<Embedded<E::Model>>, AppError>
+where
+    E: Include<Model: FromQueryResult + 'static>,
+{
+    // 先检查一遍, 以免参数有误时还要白白查询一次
+    include.check::<E>()?;
+    let page = Page::fetch(select, db, param).await?;
+    Ok(Page {
+        param: page.param,
+        total: page.total,
+        total_items: page.total_items,
+        total_pages: page.total_pages,
+        items: embed::<E>(page.items, include, db).await?,
+    })
+}
+
+/// 和 [CursorPage::fetch] 一样按游标取出一段数据, 再嵌入 `include` 中指定的关联数据
+pub async fn fetch_cursor<E, K>(
+    select: Select<E>,
+    db: &DatabaseConnection,
+    param: CursorParam,
+    include: &IncludeParam,
+) -> Result<CursorPage<Embedded<E::Model>>, AppError>
+where
+    E: Include<Model: FromQueryResult + 'static>,
+    K: DeserializeOwned + Into<<E::PrimaryKey as PrimaryKeyTrait>::ValueType>,
+{
+    include.check::<E>()?;
+    let cursor = select.cursor_by(page::primary_key::<E>());
+    let page = CursorPage::fetch::<E, K, _>(cursor, db, param).await?;
+    Ok(CursorPage {
+        limit: page.limit,
+        items: embed::<E>(page.items, include, db).await?,
+        next_cursor: page.next_cursor,
+        prev_cursor: page.prev_cursor,
+    })
+}
+
+/// 取出 `select` 的第一条记录, 再嵌入 `include` 中指定的关联数据
+pub async fn fetch_one<E>(
+    select: Select<E>,
+    db: &DatabaseConnection,
+    include: &IncludeParam,
+) -> Result<Option<Embedded<E::Model>>, AppError>
+where
+    E: Include,
+{
+    include.check::<E>()?;
+    let Some(item) = select.one(db).await? else {
+        return Ok(None);
+    };
+    Ok(embed::<E>(vec![item], include, db).await?.pop())
+}
+
+/// 加载 belongs_to 的关联, 没有关联的行为 null
+async fn one<M, R>(models: &[M], db: &DatabaseConnection) -> Result<Vec<Value>, DbErr>
+where
+    M: sea_orm::ModelTrait + Sync,
+    M::Entity: Related<R>,
+    R: EntityTrait<Model: Serialize + Send + Sync>,
+{
+    let related = models.load_one(R::default(), db).await?;
+    related.iter().map(to_value).collect()
+}
+
+/// 加载 has_many 的关联, 没有关联的行为空数组
+async fn many<M, R>(models: &[M], db: &DatabaseConnection) -> Result<Vec<Value>, DbErr>
+where
+    M: sea_orm::ModelTrait + Sync,
+    M::Entity: Related<R>,
+    R: EntityTrait<Model: Serialize + Send + Sync>,
+{
+    let related = models.load_many(R::default(), db).await?;
+    related.iter().map(to_value).collect()
+}
+
+fn to_value<T: Serialize>(value: &T) -> Result<Value, DbErr> {
+    serde_json::to_value(value).map_err(|e| DbErr::Json(e.to_string()))
+}
+
+impl Include for Student {
+    const INCLUDES: &'static [&'static str] = &["department", "scores"];
+
+    async fn load(
+        relation: &str,
+        models: &[student::Model],
+        db: &DatabaseConnection,
+    ) -> Result<Vec<Value>, DbErr> {
+        match relation {
+            "department" => one::<_, Department>(models, db).await,
+            "scores" => many::<_, Score>(models, db).await,
+            _ => unreachable!("关联名已经检查过了"),
+        }
+    }
+}
+
+impl Include for Course {
+    const INCLUDES: &'static [&'static str] = &["department", "pre_course", "scores"];
+
+    /// `pre_course` 字段已经是先修课程的 id 了
+    fn key(relation: &str) -> String {
+        match relation {
+            "pre_course" => "pre_course_detail".to_string(),
+            _ => relation.to_string(),
+        }
+    }
+
+    async fn load(
+        relation: &str,
+        models: &[course::Model],
+        db: &DatabaseConnection,
+    ) -> Result<Vec<Value>, DbErr> {
+        match relation {
+            "department" => one::<_, Department>(models, db).await,
+            "scores" => many::<_, Score>(models, db).await,
+            // 先修课程是自关联, SeaORM 的 loader 不支持, 所以手动按 id 批量查询
+            "pre_course" => {
+                let ids: Vec<&String> = models
+                    .iter()
+                    .filter_map(|m| m.pre_course.as_ref())
+                    .collect();
+                let courses: HashMap<String, course::Model> = Course::find()
+                    .filter(course::Column::Id.is_in(ids))
+                    .all(db)
+                    .await?
+                    .into_iter()
+                    .map(|c| (c.id.clone(), c))
+                    .collect();
+                models
+                    .iter()
+                    .map(|m| to_value(&m.pre_course.as_ref().and_then(|id| courses.get(id))))
+                    .collect()
+            }
+            _ => unreachable!("关联名已经检查过了"),
+        }
+    }
+}
+
+impl Include for Department {
+    const INCLUDES: &'static [&'static str] = &["students", "courses"];
+
+    async fn load(
+        relation: &str,
+        models: &[department::Model],
+        db: &DatabaseConnection,
+    ) -> Result<Vec<Value>, DbErr> {
+        match relation {
+            "students" => many::<_, Student>(models, db).await,
+            "courses" => many::<_, Course>(models, db).await,
+            _ => unreachable!("关联名已经检查过了"),
+        }
+    }
+}
+
+impl Include for Score {
+    const INCLUDES: &'static [&'static str] = &["student", "course"];
+
+    async fn load(
+        relation: &str,
+        models: &[score::Model],
+        db: &DatabaseConnection,
+    ) -> Result<Vec<Value>, DbErr> {
+        match relation {
+            "student" => one::<_, Student>(models, db).await,
+            "course" => one::<_, Course>(models, db).await,
+            _ => unreachable!("关联名已经检查过了"),
+        }
+    }
+}
+
+impl Include for ScoreHistory {
+    const INCLUDES: &'static [&'static str] = &["student", "course"];
+
+    async fn load(
+        relation: &str,
+        models: &[score_history::Model],
+        db: &DatabaseConnection,
+    ) -> Result<Vec<Value>, DbErr> {
+        match relation {
+            "student" => one::<_, Student>(models, db).await,
+            "course" => one::<_, Course>(models, db).await,
+            _ => unreachable!("关联名已经检查过了"),
+        }
+    }
+}
+
+impl Include for Term {
+    const INCLUDES: &'static [&'static str] = &[];
+
+    async fn load(
+        _relation: &str,
+        _models: &[term::Model],
+        _db: &DatabaseConnection,
+    ) -> Result<Vec<Value>, DbErr> {
+        unreachable!("学期没有可以嵌入的关联")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_include_param() {
+        let param: IncludeParam =
+            serde_json::from_value(Value::String("department, scores,,".to_string())).unwrap();
+        assert_eq!(
+            param,
+            IncludeParam(vec!["department".to_string(), "scores".to_string()])
+        );
+        assert!(param.check::<Student>().is_ok());
+        assert!(param.check::<Course>().is_ok());
+        assert!(param.check::<Department>().is_err());
+    }
+}
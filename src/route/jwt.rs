@@ -74,12 +74,15 @@ impl<T: Serialize + for<'de> Deserialize<'de>> Jwt<T> {
         res
     }
 
-    /// 通过 token 解码出 load，val 参数为校验配置，见 [`jsonwebtoken::Validation`]
+    /// 通过 token 解码出 load 和这个 jwt 的过期时间，val 参数为校验配置，见 [`jsonwebtoken::Validation`]
     /// 默认的 val 参数可以传递 [`crate::route::jwt`] 模块中的 常量 [`crate::route::jwt::DEFAULT_VALIDATION`]
-    pub fn decode_with(token: &str, val: &Validation) -> anyhow::Result<T> {
+    pub fn decode_with(token: &str, val: &Validation) -> anyhow::Result<(T, SystemTime)> {
         let res = jsonwebtoken::decode::<Self>(token, &DECODING_KEY, val);
         match res {
-            Ok(res) => Ok(res.claims.load),
+            Ok(res) => Ok((
+                res.claims.load,
+                UNIX_EPOCH + Duration::from_secs(res.claims.claims.exp),
+            )),
             Err(err) => Err(anyhow::anyhow!(err)),
         }
     }
@@ -109,10 +112,12 @@ mod test {
         let load = TestLoad::default();
         let jwt = Jwt::new(load.clone(), &Duration::from_secs(60));
         let token = jwt.encode_with(Algorithm::HS256);
-        let decoded =
+        let (decoded, expires_at) =
             Jwt::<TestLoad>::decode_with(&token, &Validation::new(Algorithm::HS256)).unwrap();
         println!("{token}");
         assert_eq!(load, decoded);
+        assert!(expires_at > SystemTime::now());
+        assert!(expires_at <= SystemTime::now() + Duration::from_secs(60));
     }
 
     #[test]
@@ -122,8 +127,8 @@ mod test {
         let token = jwt.encode_with(Algorithm::HS256);
         let decoded = Jwt::<TestLoad>::decode_with(&token, &DEFAULT_VALIDATION);
         println!("{token}");
-        assert!(!decoded.is_err());
-        assert_eq!(load, decoded.unwrap());
+        assert!(decoded.is_ok());
+        assert_eq!(load, decoded.unwrap().0);
 
         std::thread::sleep(Duration::from_secs(2));
 
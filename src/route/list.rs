@@ -0,0 +1,63 @@
+use schemars::{Schema, json_schema};
+use serde::de::DeserializeOwned;
+use serde::de::value::StrDeserializer;
+use serde::{Deserialize, Deserializer};
+
+/// 为以逗号分隔的列表参数 `$name(Vec<T>)` 实现 `Deserialize` 和 `JsonSchema`, `$example` 是文档中的示例
+///
+/// ```ignore
+/// pub struct IncludeParam(Vec<String>);
+///
+/// comma_list!(IncludeParam, "department,scores");
+/// ```
+#[macro_export]
+macro_rules! comma_list {
+    ($name: ident, $example: literal) => {
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                $crate::route::list::deserialize(deserializer).map($name)
+            }
+        }
+
+        impl schemars::JsonSchema for $name {
+            fn schema_name() -> std::borrow::Cow<'static, str> {
+                stringify!($name).into()
+            }
+
+            fn inline_schema() -> bool {
+                true
+            }
+
+            fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+                $crate::route::list::schema($example)
+            }
+        }
+    };
+}
+
+/// 拆分形如 `a, b,c` 的列表, 每一项去掉首尾的空白, 空的项被忽略
+pub fn split(s: &str) -> impl Iterator<Item = &str> {
+    s.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+/// 将以逗号分隔的字符串反序列化为列表, 每一项都按 `T` 反序列化, 重复的项只保留第一个
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    T: DeserializeOwned + PartialEq,
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    let mut items = Vec::new();
+    for item in split(&s) {
+        let item = T::deserialize(StrDeserializer::<D::Error>::new(item))?;
+        if !items.contains(&item) {
+            items.push(item);
+        }
+    }
+    Ok(items)
+}
+
+/// 列表参数在查询字符串中仍然是一个字符串
+pub fn schema(example: &str) -> Schema {
+    json_schema!({"type": "string", "examples": [example]})
+}
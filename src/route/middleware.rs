@@ -1,13 +1,13 @@
-use std::{pin::Pin, sync::LazyLock};
+use std::{pin::Pin, sync::LazyLock, time::SystemTime};
 
 use axum::{
     body::Body,
     extract::Request,
     http::{Response, header},
-    response::IntoResponse,
 };
 use tower_http::auth::{AsyncAuthorizeRequest, AsyncRequireAuthorizationLayer};
 
+use crate::t;
 use crate::{
     error::AppError,
     route::{
@@ -36,27 +36,38 @@ impl AsyncAuthorizeRequest<Body> for Auth {
 
     fn authorize(&mut self, mut request: Request<Body>) -> Self::Future {
         Box::pin(async move {
-            let auth_header = request.headers().get(header::AUTHORIZATION);
-
-            if auth_header.is_none() {
-                Err(AppError::Unauthorized("你还未登录!".to_string()).into_response())
-            } else {
-                let auth_header = auth_header.unwrap().to_str().map_err(|e| {
-                    AppError::BadRequest(format!("找到一个无法被现有编码支持的字符, 详细信息: {e}"))
-                })?;
-
-                let token = auth_header.strip_prefix("Bearer ").ok_or_else(|| {
-                    AppError::BadRequest("Authorization 字段应该以 \"Bearer \" 开头".to_string())
-                })?;
-
-                let usr_ident =
-                    Jwt::<UserIdent>::decode_with(token, &DEFAULT_VALIDATION).map_err(|e| {
-                        AppError::Unauthorized(format!("JWT 校验未通过, 详细信息: {e}"))
-                    })?;
-                request.extensions_mut().insert(usr_ident);
-
-                Ok(request)
-            }
+            let auth_header = request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .map(|v| {
+                    v.to_str()
+                        .map_err(|e| AppError::BadRequest(t!("auth.bad_encoding", error = e)))
+                })
+                .transpose()?;
+
+            let usr_ident = authenticate(auth_header)?;
+            request.extensions_mut().insert(usr_ident);
+
+            Ok(request)
         })
     }
 }
+
+/// 校验 `Authorization` 中的 JWT 并返回其中的用户信息, gRPC 服务也通过这个函数鉴权
+pub fn authenticate(auth_header: Option<&str>) -> Result<UserIdent, AppError> {
+    authenticate_until(auth_header).map(|(usr, _)| usr)
+}
+
+/// 和 [authenticate] 相同, 同时返回 token 的过期时间, 长连接在这个时间之后会被断开
+pub fn authenticate_until(auth_header: Option<&str>) -> Result<(UserIdent, SystemTime), AppError> {
+    let Some(auth_header) = auth_header else {
+        return Err(AppError::Unauthorized(t!("auth.not_logged_in")));
+    };
+
+    let token = auth_header
+        .strip_prefix("Bearer ")
+        .ok_or_else(|| AppError::BadRequest(t!("auth.bearer_prefix")))?;
+
+    Jwt::<UserIdent>::decode_with(token, &DEFAULT_VALIDATION)
+        .map_err(|e| AppError::Unauthorized(t!("auth.bad_token", error = e)))
+}
@@ -2,12 +2,25 @@ use crate::route::result::AppResult;
 use crate::server::ServerState;
 use axum::Router;
 
+pub mod crud;
+pub mod etag;
+pub mod export;
 mod extract;
+pub mod fields;
+pub mod filter;
+pub mod idempotency;
+pub mod include;
 pub mod jwt;
+pub mod list;
 pub mod middleware;
+pub mod openapi;
 pub mod page;
+pub mod patch;
 pub mod request;
 pub mod result;
+pub mod routes;
+pub mod sort;
+pub mod version;
 
 pub fn build_router() -> Router<ServerState> {
     request::build_router()
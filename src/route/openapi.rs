@@ -0,0 +1,570 @@
+use crate::error::{ErrorResponse, ProblemDetails};
+use crate::route::filter::Filters;
+use crate::route::request;
+use crate::route::routes::Routes;
+use crate::route::version::{self, ApiVersion, Deprecated};
+use axum::Json;
+use axum::http::header;
+use axum::response::{Html, IntoResponse};
+use schemars::generate::SchemaSettings;
+use schemars::{JsonSchema, Schema, SchemaGenerator};
+use sea_orm::IdenStatic;
+use serde_json::{Map, Value, json};
+use std::sync::LazyLock;
+
+/// 各个版本的 OpenAPI 文档, 在第一次请求时生成
+static V1: LazyLock<Value> = LazyLock::new(|| spec(ApiVersion::V1));
+static V2: LazyLock<Value> = LazyLock::new(|| spec(ApiVersion::V2));
+
+pub fn router() -> Routes {
+    Routes::new()
+        .get("/openapi.json", openapi)
+        .get("/docs", swagger_ui)
+        .get("/redoc", redoc)
+}
+
+/// Swagger UI 5.17.14 的脚本和样式, 编译进程序中, 在无法访问外网的环境中也能打开调试页面
+const SWAGGER_UI_JS: &str = include_str!("../../assets/swagger-ui/swagger-ui-bundle.js");
+const SWAGGER_UI_CSS: &str = include_str!("../../assets/swagger-ui/swagger-ui.css");
+
+/// 接口文档页面所需的静态资源, 各版本共用, 不在接口文档中
+pub fn assets() -> Routes {
+    Routes::new()
+        .get("/assets/swagger-ui/swagger-ui-bundle.js", swagger_ui_js)
+        .get("/assets/swagger-ui/swagger-ui.css", swagger_ui_css)
+}
+
+async fn swagger_ui_js() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/javascript; charset=utf-8")],
+        SWAGGER_UI_JS,
+    )
+}
+
+async fn swagger_ui_css() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/css; charset=utf-8")],
+        SWAGGER_UI_CSS,
+    )
+}
+
+/// 生成 `version` 版本的 API 的 OpenAPI 3.1 文档
+pub fn spec(version: ApiVersion) -> Value {
+    let mut doc = ApiDoc::new(version);
+    request::document(&mut doc);
+    doc.into_json()
+}
+
+/// 为生成路由中的文档提供类型的 JSON Schema
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+/// 一个接口的文档
+///
+/// 路径参数会从路径中自动提取, 查询参数和请求体由实现了 [JsonSchema] 的参数类型生成,
+/// `validator` 的约束 (长度, 范围, email 等) 会被 schemars 一并转换为 JSON Schema
+pub struct Operation {
+    summary: String,
+    description: Option<String>,
+    public: bool,
+    deprecated: Option<Deprecated>,
+    params: Vec<Value>,
+    path: Vec<(&'static str, SchemaFn)>,
+    query: Vec<SchemaFn>,
+    body: Option<(&'static [&'static str], Option<SchemaFn>)>,
+    response: (&'static str, Option<SchemaFn>),
+    errors: Vec<(&'static str, &'static str)>,
+}
+
+impl Operation {
+    pub fn new(summary: impl Into<String>) -> Self {
+        Operation {
+            summary: summary.into(),
+            description: None,
+            public: false,
+            deprecated: None,
+            params: Vec::new(),
+            path: Vec::new(),
+            query: Vec::new(),
+            body: None,
+            response: ("application/json", None),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// 不需要登录就可以访问的接口
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    /// 已弃用的接口, 路由上需要同时加上 [version::deprecated] 中间件
+    pub fn deprecated(mut self, deprecated: Deprecated) -> Self {
+        self.deprecated = Some(deprecated);
+        self
+    }
+
+    /// 类型 `T` 的每一个字段都是一个查询参数
+    pub fn query<T: JsonSchema>(mut self) -> Self {
+        self.query.push(T::json_schema);
+        self
+    }
+
+    /// 路径参数 `name` 的类型, 没有指定类型的路径参数都视为字符串
+    pub fn path<T: JsonSchema>(mut self, name: &'static str) -> Self {
+        self.path.push((name, |g| g.subschema_for::<T>()));
+        self
+    }
+
+    /// `filter[字段][操作]=值` 形式的筛选参数, 只允许按 `columns` 中的列筛选
+    pub fn filter<C: IdenStatic>(mut self, columns: &[C]) -> Self {
+        let fields: Vec<_> = columns.iter().map(|c| c.as_str()).collect();
+        self.params.push(json!({
+            "name": "filter",
+            "in": "query",
+            "style": "deepObject",
+            "explode": true,
+            "description": format!(
+                "筛选条件, 形如 `filter[字段][操作]=值`, 省略操作时为 eq. 可以筛选的字段有: {}. 可用的操作有: {}.",
+                fields.join(", "),
+                Filters::OPS,
+            ),
+            "schema": {"type": "object", "additionalProperties": {"type": "string"}},
+        }));
+        self
+    }
+
+    /// JSON 请求体
+    pub fn json<T: JsonSchema>(mut self) -> Self {
+        self.body = Some((&["application/json"], Some(|g| g.subschema_for::<T>())));
+        self
+    }
+
+    /// 非 JSON 的请求体, 如上传的文件
+    pub fn body(mut self, content_types: &'static [&'static str]) -> Self {
+        self.body = Some((content_types, None));
+        self
+    }
+
+    /// JSON 响应
+    pub fn returns<T: JsonSchema>(mut self) -> Self {
+        self.response = ("application/json", Some(|g| g.subschema_for::<T>()));
+        self
+    }
+
+    /// 非 JSON 的响应, 如 PDF 或 HTML
+    pub fn returns_content(mut self, content_type: &'static str) -> Self {
+        self.response = (content_type, None);
+        self
+    }
+
+    /// 需要在 If-Match 请求头中带上读取时得到的 ETag, 见 [`crate::route::etag::IfMatch`]
+    pub fn if_match(mut self) -> Self {
+        self.params.push(json!({
+            "name": "If-Match",
+            "in": "header",
+            "required": true,
+            "description": "按主键获取这条记录时得到的 ETag, 记录在这之后被修改过时返回 412.",
+            "schema": {"type": "string"},
+        }));
+        self.error("412", "记录在读取之后已经被修改过了")
+            .error("428", "缺少 If-Match 请求头")
+    }
+
+    /// 需要登录的 POST 接口都支持 Idempotency-Key, 见 [`crate::route::idempotency`]
+    fn idempotent(mut self) -> Self {
+        if self.public {
+            return self;
+        }
+        self.params.push(json!({
+            "name": "Idempotency-Key",
+            "in": "header",
+            "required": false,
+            "description": "重试时带上同一个值, 服务器会直接返回第一次请求的响应, 而不会再执行一次.",
+            "schema": {"type": "string", "minLength": 1, "maxLength": 255},
+        }));
+        self.error("409", "带有相同 Idempotency-Key 的请求还在处理中")
+            .error(
+                "422",
+                "参数没有通过校验, 或者 Idempotency-Key 已经被用于另一个不同的请求",
+            )
+    }
+
+    /// 除了根据参数推断出的错误之外, 接口还可能返回的错误
+    pub fn error(mut self, status_code: &'static str, description: &'static str) -> Self {
+        self.errors.push((status_code, description));
+        self
+    }
+
+    fn into_json(self, path: &str, tag: &str, generator: &mut SchemaGenerator) -> Value {
+        let mut params = self.params;
+        for name in path_params(path) {
+            let schema = match self.path.iter().find(|(typed, _)| *typed == name) {
+                Some((_, schema)) => schema(generator).to_value(),
+                None => json!({"type": "string"}),
+            };
+            params.push(json!({
+                "name": name,
+                "in": "path",
+                "required": true,
+                "schema": schema,
+            }));
+        }
+        for schema in &self.query {
+            params.extend(query_params(schema(generator)));
+        }
+
+        let mut operation = json!({
+            "tags": [tag],
+            "summary": self.summary,
+            "parameters": params,
+        });
+        let description = match (self.description, self.deprecated) {
+            (Some(description), Some(deprecated)) => {
+                Some(format!("{} {description}", deprecated.describe()))
+            }
+            (description, deprecated) => description.or(deprecated.map(|d| d.describe())),
+        };
+        if let Some(description) = description {
+            operation["description"] = description.into();
+        }
+        if self.deprecated.is_some() {
+            operation["deprecated"] = true.into();
+        }
+        if self.public {
+            operation["security"] = json!([]);
+        }
+        if let Some((content_types, schema)) = self.body {
+            let schema = schema.map_or(json!({}), |schema| schema(generator).to_value());
+            let content: Map<_, _> = content_types
+                .iter()
+                .map(|content_type| (content_type.to_string(), json!({"schema": schema})))
+                .collect();
+            operation["requestBody"] = json!({"required": true, "content": content});
+        }
+
+        let (content_type, schema) = self.response;
+        let schema = schema.map_or(json!({}), |schema| schema(generator).to_value());
+        let mut responses = Map::new();
+        responses.insert(
+            "200".into(),
+            json!({"description": "成功", "content": {content_type: {"schema": schema}}}),
+        );
+        let mut errors = self.errors;
+        if !self.query.is_empty() || !operation["parameters"].as_array().unwrap().is_empty() {
+            errors.push(("400", "请求参数有误"));
+        }
+        if !self.public {
+            errors.extend([("401", "没有登录或者登录已过期"), ("403", "没有权限")]);
+        }
+        if path.contains('{') {
+            errors.push(("404", "没有相关的记录"));
+        }
+        if !self.query.is_empty() || operation.get("requestBody").is_some() {
+            errors.push(("422", "参数没有通过校验"));
+        }
+        errors.push(("500", "服务器或数据库出错"));
+        // 显式声明的错误在前面, 不会被推断出来的同一状态码的错误覆盖
+        for (code, description) in errors {
+            responses.entry(code).or_insert_with(|| {
+                json!({
+                    "description": description,
+                    "content": {
+                        "application/problem+json": {"schema": {"$ref": "#/components/schemas/ProblemDetails"}},
+                        "application/json": {"schema": {"$ref": "#/components/schemas/ErrorResponse"}},
+                    },
+                })
+            });
+        }
+        operation["responses"] = responses.into();
+        operation
+    }
+}
+
+/// 按照路由的结构收集各个接口的文档
+pub struct ApiDoc {
+    generator: SchemaGenerator,
+    version: ApiVersion,
+    prefix: String,
+    tag: String,
+    paths: Map<String, Value>,
+}
+
+impl ApiDoc {
+    fn new(version: ApiVersion) -> Self {
+        let settings = SchemaSettings::draft2020_12().with(|settings| {
+            settings.definitions_path = "/components/schemas".into();
+        });
+        let mut generator = settings.into_generator();
+        generator.subschema_for::<ErrorResponse>();
+        generator.subschema_for::<ProblemDetails>();
+        ApiDoc {
+            generator,
+            version,
+            prefix: format!("/api{}", version.prefix()),
+            tag: String::new(),
+            paths: Map::new(),
+        }
+    }
+
+    /// 正在生成文档的接口版本, 各版本的路由不同时用它区分
+    pub fn version(&self) -> ApiVersion {
+        self.version
+    }
+
+    /// 和 [Router::nest] 对应, `document` 中的路径都以 `prefix` 开头, 并归入 `tag` 分组
+    pub fn nest(
+        &mut self,
+        prefix: &str,
+        tag: &str,
+        document: impl FnOnce(&mut ApiDoc),
+    ) -> &mut Self {
+        let inner_prefix = format!("{}{prefix}", self.prefix);
+        let outer_prefix = std::mem::replace(&mut self.prefix, inner_prefix);
+        let outer_tag = std::mem::replace(&mut self.tag, tag.to_string());
+        document(self);
+        self.prefix = outer_prefix;
+        self.tag = outer_tag;
+        self
+    }
+
+    pub fn get(&mut self, path: &str, operation: Operation) -> &mut Self {
+        self.operation("get", path, operation)
+    }
+
+    pub fn post(&mut self, path: &str, operation: Operation) -> &mut Self {
+        self.operation("post", path, operation.idempotent())
+    }
+
+    pub fn put(&mut self, path: &str, operation: Operation) -> &mut Self {
+        self.operation("put", path, operation)
+    }
+
+    pub fn patch(&mut self, path: &str, operation: Operation) -> &mut Self {
+        self.operation("patch", path, operation)
+    }
+
+    pub fn delete(&mut self, path: &str, operation: Operation) -> &mut Self {
+        self.operation("delete", path, operation)
+    }
+
+    fn operation(&mut self, method: &str, path: &str, operation: Operation) -> &mut Self {
+        // 和 axum 一样, 嵌套路由中的 "/" 对应的就是前缀本身
+        let path = match path {
+            "/" => self.prefix.clone(),
+            _ => format!("{}{path}", self.prefix),
+        };
+        let operation = operation.into_json(&path, &self.tag, &mut self.generator);
+        let item = self.paths.entry(path).or_insert_with(|| json!({}));
+        item[method] = operation;
+        self
+    }
+
+    fn into_json(mut self) -> Value {
+        let schemas = self.generator.take_definitions(true);
+        json!({
+            "openapi": "3.1.0",
+            "info": {
+                "title": format!("web-start {}", &self.version.prefix()[1..]),
+                "description": "学生成绩管理系统的后端接口. 除了登录和成绩单核验之外, 所有接口都需要在请求头中带上登录时返回的 token: `Authorization: Bearer <token>`.",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "jsonSchemaDialect": "https://json-schema.org/draft/2020-12/schema",
+            "security": [{"bearer": []}],
+            "paths": self.paths,
+            "components": {
+                "schemas": schemas,
+                "securitySchemes": {
+                    "bearer": {"type": "http", "scheme": "bearer", "bearerFormat": "JWT"},
+                },
+            },
+        })
+    }
+}
+
+/// 取出路径中的参数名, 如 `/score/{stu_id}/{course_id}` 中的 `stu_id` 和 `course_id`
+fn path_params(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+}
+
+/// 把一个对象的 JSON Schema 展开为查询参数, 每一个属性对应一个参数
+fn query_params(schema: Schema) -> Vec<Value> {
+    let schema = schema.to_value();
+    let required = schema["required"].as_array().cloned().unwrap_or_default();
+    let Some(properties) = schema["properties"].as_object() else {
+        return Vec::new();
+    };
+    properties
+        .iter()
+        .map(|(name, property)| {
+            let mut param = json!({
+                "name": name,
+                "in": "query",
+                "required": required.contains(&Value::from(name.as_str())),
+                "schema": property,
+            });
+            if let Some(description) = property.get("description") {
+                param["description"] = description.clone();
+            }
+            param
+        })
+        .collect()
+}
+
+/// 返回当前请求所用版本的 OpenAPI 文档
+async fn openapi() -> Json<&'static Value> {
+    match version::current() {
+        ApiVersion::V1 => Json(&V1),
+        ApiVersion::V2 => Json(&V2),
+    }
+}
+
+/// 当前请求所用版本的 OpenAPI 文档的地址
+fn spec_url() -> String {
+    format!("/api{}/openapi.json", version::current().prefix())
+}
+
+/// 基于 Swagger UI 的接口调试页面
+async fn swagger_ui() -> Html<String> {
+    Html(
+        r##"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+  <meta charset="utf-8">
+  <title>web-start API</title>
+  <link rel="stylesheet" href="/assets/swagger-ui/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="/assets/swagger-ui/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "{spec_url}", dom_id: "#swagger-ui", persistAuthorization: true });
+  </script>
+</body>
+</html>"##
+            .replace("{spec_url}", &spec_url()),
+    )
+}
+
+/// 基于 Redoc 的接口文档页面, Redoc 的脚本来自 CDN, 无法加载时提示改用 Swagger UI 页面
+async fn redoc() -> Html<String> {
+    Html(
+        r#"<!DOCTYPE html>
+<html lang="zh-CN">
+<head>
+  <meta charset="utf-8">
+  <title>web-start API</title>
+</head>
+<body>
+  <redoc spec-url="{spec_url}"></redoc>
+  <p id="offline" hidden>无法从 CDN 加载 Redoc, 请使用 <a href="docs">Swagger UI 页面</a>.</p>
+  <script src="https://cdn.redoc.ly/redoc/v2.1.5/bundles/redoc.standalone.js" crossorigin="anonymous"
+          referrerpolicy="no-referrer" onerror="document.getElementById('offline').hidden = false"></script>
+</body>
+</html>"#
+            .replace("{spec_url}", &spec_url()),
+    )
+}
+
+/// 文档中和本模块提供的接口
+pub fn document(doc: &mut ApiDoc) {
+    doc.get(
+        "/openapi.json",
+        Operation::new("获取 OpenAPI 文档").public(),
+    )
+    .get(
+        "/docs",
+        Operation::new("Swagger UI 调试页面")
+            .public()
+            .returns_content("text/html"),
+    )
+    .get(
+        "/redoc",
+        Operation::new("Redoc 文档页面")
+            .public()
+            .returns_content("text/html"),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// 路由表中的所有 (方法, 路径), 方法为小写, 和文档中的一致
+    fn routes() -> BTreeSet<(String, String)> {
+        request::routes()
+            .list()
+            .map(|(method, path)| (method.as_str().to_lowercase(), path.to_string()))
+            .collect()
+    }
+
+    /// 文档中的所有 (方法, 路径)
+    fn documented(spec: &Value) -> BTreeSet<(String, String)> {
+        spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, item)| {
+                item.as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.clone(), path.clone()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_every_route_is_documented() {
+        let routes = routes();
+        assert!(routes.contains(&("get".to_string(), "/api/v1/student/query".to_string())));
+
+        for version in ApiVersion::ALL {
+            let prefix = format!("/api{}", version.prefix());
+            let routes: BTreeSet<_> = routes
+                .iter()
+                .filter(|(_, path)| path.starts_with(&prefix))
+                .cloned()
+                .collect();
+            let documented = documented(&spec(version));
+
+            let undocumented: Vec<_> = routes.difference(&documented).collect();
+            assert!(
+                undocumented.is_empty(),
+                "{prefix} 中这些路由没有文档: {undocumented:?}"
+            );
+            let missing: Vec<_> = documented.difference(&routes).collect();
+            assert!(
+                missing.is_empty(),
+                "{prefix} 中这些文档没有对应的路由: {missing:?}"
+            );
+        }
+    }
+
+    /// `/api` 是 v1 的别名, 两者的路由应该完全相同
+    #[test]
+    fn test_unversioned_alias() {
+        let routes = routes();
+        let versioned = |path: &str| {
+            ApiVersion::ALL
+                .iter()
+                .any(|version| path.starts_with(&format!("/api{}", version.prefix())))
+        };
+        let alias: BTreeSet<_> = routes
+            .iter()
+            .filter(|(_, path)| path.starts_with("/api") && !versioned(path))
+            .map(|(method, path)| (method.clone(), path.replacen("/api", "/api/v1", 1)))
+            .collect();
+        let v1: BTreeSet<_> = routes
+            .into_iter()
+            .filter(|(_, path)| path.starts_with("/api/v1"))
+            .collect();
+        assert!(!alias.is_empty());
+        assert_eq!(alias, v1);
+    }
+}
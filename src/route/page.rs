@@ -1,25 +1,66 @@
 use std::{fmt::Display, str::FromStr};
 
+use crate::error::AppError;
+use crate::route::version::{self, ApiVersion};
+use crate::t;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use schemars::JsonSchema;
+use sea_orm::sea_query::{DynIden, SeaRc};
+use sea_orm::{
+    Cursor, DatabaseConnection, DbErr, EntityTrait, IdenStatic, Identity, Iterable, PaginatorTrait,
+    PrimaryKeyToColumn, PrimaryKeyTrait, SelectorTrait,
+};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Deserializer, Serialize};
 use validator::Validate;
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "Page_of_{T}")]
 pub struct Page<T: Serialize> {
     #[serde(flatten)]
     pub param: PageParam,
-    pub total: u64,
+    /// 总页数, 和 `total_pages` 相同, 为了兼容以前的客户端只在 v1 中保留
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
+    pub total_items: u64,
+    pub total_pages: u64,
     pub items: Vec<T>,
 }
 
-#[derive(Serialize, Deserialize, Validate, Clone, Copy)]
+impl<T: Serialize + Send + Sync> Page<T> {
+    /// 按照 `param` 取出 `select` 的一页数据
+    pub async fn fetch<'db, S>(
+        select: S,
+        db: &'db DatabaseConnection,
+        param: PageParam,
+    ) -> Result<Self, DbErr>
+    where
+        S: PaginatorTrait<'db, DatabaseConnection, Selector: SelectorTrait<Item = T>>,
+    {
+        let pagination = select.paginate(db, param.size);
+        let total = pagination.num_items_and_pages().await?;
+        let items = pagination.fetch_page(param.index - 1).await?;
+
+        Ok(Page {
+            param,
+            total: (version::current() == ApiVersion::V1).then_some(total.number_of_pages),
+            total_items: total.number_of_items,
+            total_pages: total.number_of_pages,
+            items,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Validate, JsonSchema, Clone, Copy)]
 pub struct PageParam {
     /// 代表现在是第几页
-    #[validate(range(min = 1, message = "页码应大于等于 1."))]
+    #[validate(range(min = 1, code = "page_index"))]
     #[serde(default = "PageParam::default_index", deserialize_with = "from_str")]
     pub index: u64,
 
     /// 代表一页能容纳多少条数据
-    #[validate(range(min = 1, max = 100, message = "每页所含信息应在 1 条至 100 条之间."))]
+    #[validate(range(min = 1, max = 100, code = "page_size"))]
     #[serde(default = "PageParam::default_size", deserialize_with = "from_str")]
     pub size: u64,
 }
@@ -37,6 +78,149 @@ impl PageParam {
     }
 }
 
+impl Default for PageParam {
+    fn default() -> Self {
+        PageParam {
+            index: PageParam::DEFAULT_PAGE_INDEX,
+            size: PageParam::DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+/// 游标分页的参数, `after` 和 `before` 分别取自上一次结果中的 `next_cursor` 和 `prev_cursor`
+///
+/// 游标是不透明的字符串, 只按主键定位, 因此在并发插入时也不会出现重复或遗漏
+#[derive(Deserialize, Validate, JsonSchema, Default)]
+pub struct CursorParam {
+    pub after: Option<String>,
+    pub before: Option<String>,
+
+    /// 每次最多返回多少条数据
+    #[validate(range(min = 1, max = 100, code = "page_size"))]
+    #[serde(default, deserialize_with = "option_from_str")]
+    pub limit: Option<u64>,
+}
+
+impl CursorParam {
+    /// 请求中出现了任意一个游标分页的参数时使用游标分页, 否则使用原来的按页码分页
+    pub fn is_present(&self) -> bool {
+        self.after.is_some() || self.before.is_some() || self.limit.is_some()
+    }
+}
+
+/// 游标分页的结果, 没有更多数据时对应的游标为 null
+#[derive(Serialize, JsonSchema)]
+#[schemars(rename = "CursorPage_of_{T}")]
+pub struct CursorPage<T: Serialize> {
+    pub limit: u64,
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+}
+
+impl<T: Serialize> CursorPage<T> {
+    /// 按照 `param` 取出 `cursor` 的一段数据, `cursor` 需要以 [primary_key] 为游标列,
+    /// `K` 是实体 `E` 的主键反序列化后的类型
+    ///
+    /// 游标分页总是按主键升序排列, 查询原有的排序会被忽略
+    pub async fn fetch<E, K, S>(
+        mut cursor: Cursor<S>,
+        db: &DatabaseConnection,
+        param: CursorParam,
+    ) -> Result<Self, AppError>
+    where
+        E: EntityTrait,
+        K: DeserializeOwned + Into<<E::PrimaryKey as PrimaryKeyTrait>::ValueType>,
+        S: SelectorTrait<Item = T>,
+    {
+        let limit = param.limit.unwrap_or(PageParam::DEFAULT_PAGE_SIZE);
+        // 多取一条, 以判断后面 (或前面) 是否还有数据
+        match (&param.after, &param.before) {
+            (Some(_), Some(_)) => {
+                return Err(AppError::BadRequest(t!("page.after_and_before")));
+            }
+            (after, None) => {
+                if let Some(after) = after {
+                    cursor.after(decode::<K>(after)?.into());
+                }
+                cursor.first(limit + 1);
+            }
+            (None, Some(before)) => {
+                cursor.before(decode::<K>(before)?.into());
+                cursor.last(limit + 1);
+            }
+        }
+        let mut items = cursor.all(db).await?;
+
+        let has_more = items.len() as u64 > limit;
+        let (has_prev, has_next) = if param.before.is_some() {
+            if has_more {
+                items.remove(0);
+            }
+            (has_more, true)
+        } else {
+            items.truncate(limit as usize);
+            (param.after.is_some(), has_more)
+        };
+
+        let cursor_of = |item: Option<&T>| match item {
+            Some(item) => encode::<E>(item).map(Some),
+            None => Ok(None),
+        };
+        Ok(CursorPage {
+            limit,
+            next_cursor: cursor_of(items.last().filter(|_| has_next))?,
+            prev_cursor: cursor_of(items.first().filter(|_| has_prev))?,
+            items,
+        })
+    }
+}
+
+/// 实体 `E` 的主键所对应的列, 用作游标
+pub fn primary_key<E: EntityTrait>() -> Identity {
+    let mut columns: Vec<DynIden> = E::PrimaryKey::iter()
+        .map(|key| SeaRc::new(key.into_column()) as DynIden)
+        .collect();
+    match columns.len() {
+        1 => Identity::Unary(columns.remove(0)),
+        2 => Identity::Binary(columns.remove(0), columns.remove(0)),
+        3 => Identity::Ternary(columns.remove(0), columns.remove(0), columns.remove(0)),
+        _ => Identity::Many(columns),
+    }
+}
+
+/// 把一条记录的主键编码为游标, 单个主键编码为它的值, 复合主键编码为数组
+fn encode<E: EntityTrait>(item: &impl Serialize) -> Result<String, AppError> {
+    let mut json = serde_json::to_value(item)
+        .map_err(|e| AppError::Internal(t!("page.encode_cursor", error = e)))?;
+    let mut keys: Vec<_> = E::PrimaryKey::iter()
+        .map(|key| json[key.into_column().as_str()].take())
+        .collect();
+    let key = match keys.len() {
+        1 => keys.remove(0),
+        _ => serde_json::Value::Array(keys),
+    };
+    Ok(URL_SAFE_NO_PAD.encode(key.to_string()))
+}
+
+/// 把游标解码为主键
+fn decode<K: DeserializeOwned>(cursor: &str) -> Result<K, AppError> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or_else(|| AppError::BadRequest(t!("page.bad_cursor", cursor = cursor)))
+}
+
+fn option_from_str<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+    D: Deserializer<'de>,
+{
+    from_str(deserializer).map(Some)
+}
+
 fn from_str<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
     T: FromStr,
@@ -46,3 +230,32 @@ where
     let s = String::deserialize(deserializer)?;
     T::from_str(&s).map_err(serde::de::Error::custom)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entity::prelude::Score;
+    use crate::entity::score::Model;
+
+    #[test]
+    fn test_cursor() {
+        let item = Model {
+            stu_id: "170101".to_string(),
+            course_id: "020101".to_string(),
+            score: Some(88),
+            record_date: None,
+        };
+        let cursor = encode::<Score>(&item).unwrap();
+        let key: (String, String) = decode(&cursor).unwrap();
+        assert_eq!(key, (item.stu_id, item.course_id));
+
+        assert!(matches!(
+            decode::<String>(&cursor),
+            Err(AppError::BadRequest(_))
+        ));
+        assert!(matches!(
+            decode::<String>("不是游标"),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+use sea_orm::{ActiveValue, Value};
+use serde::{Deserialize, Deserializer};
+
+/// 部分更新时, 请求中出现了的字段才会被更新, 没有出现的字段保持不变
+pub fn present<T: Into<Value>>(value: Option<T>) -> ActiveValue<T> {
+    match value {
+        Some(value) => ActiveValue::Set(value),
+        None => ActiveValue::NotSet,
+    }
+}
+
+/// 用于可以为空的字段, 区分没有出现 (外层为 None) 和显式地设为 null (`Some(None)`)
+///
+/// 需要和 `#[serde(default)]` 一起使用, 否则字段缺失时会报错
+pub fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Params {
+        #[serde(default, deserialize_with = "nullable")]
+        email: Option<Option<String>>,
+    }
+
+    #[test]
+    fn test_nullable() {
+        let missing: Params = serde_json::from_str("{}").unwrap();
+        assert_eq!(missing.email, None);
+        let null: Params = serde_json::from_str(r#"{"email": null}"#).unwrap();
+        assert_eq!(null.email, Some(None));
+        let set: Params = serde_json::from_str(r#"{"email": "a@b.c"}"#).unwrap();
+        assert_eq!(set.email, Some(Some("a@b.c".to_string())));
+
+        assert_eq!(present(missing.email), ActiveValue::NotSet);
+        assert_eq!(present(null.email), ActiveValue::Set(None));
+    }
+}
@@ -1,39 +1,73 @@
+// DeriveIntoActiveModel 展开后总会带上 `..Default::default()`, InsertParams 的字段齐全时会触发这个 lint
+#![allow(clippy::needless_update)]
+
 use crate::entity::department;
-use crate::entity::department::{ActiveModel, Model};
+use crate::entity::department::ActiveModel;
 use crate::entity::prelude::Department;
 use crate::error::AppError;
-use crate::route::extract::{Path, ValidJson, ValidQuery};
-use crate::route::page::{Page, PageParam};
-use crate::route::result::AppResult;
-use crate::server::ServerState;
-use crate::throw_err;
-use axum::extract::State;
-use axum::{Router, debug_handler, routing};
+use crate::route::crud::{self, CrudResource};
+use crate::route::openapi::ApiDoc;
+use crate::route::patch;
+use crate::route::request::events::{self, Action, Topic};
+use crate::route::request::import::Importable;
+use crate::route::request::login::UserIdent;
+use crate::route::routes::Routes;
+use crate::route::version::ApiVersion;
+use async_graphql::InputObject;
+use schemars::JsonSchema;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DeriveIntoActiveModel, EntityTrait, IntoActiveModel, ModelTrait,
-    PaginatorTrait, QueryFilter, QueryOrder, QueryTrait,
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, DeriveIntoActiveModel, IntoActiveModel,
+    QueryFilter, QueryTrait, Select,
 };
 use serde::Deserialize;
 use validator::Validate;
 
-pub fn router() -> Router<ServerState> {
-    Router::new()
-        .route("/", routing::get(index))
-        .route("/query", routing::get(query))
-        .route("/insert", routing::post(insert))
-        .route("/update/{id}", routing::put(update))
-        .route("/delete/{id}", routing::delete(delete))
+pub fn router(version: ApiVersion) -> Routes {
+    crud::router::<Department>(version)
+}
+
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    crud::document::<Department>(doc);
 }
 
-/// 路由到 department 模块下的默认界面
-#[debug_handler]
-async fn index() -> AppResult<&'static str> {
-    AppResult::Ok("欢迎! 这是 Department 的首页.")
+impl CrudResource for Department {
+    const NAME: &'static str = "Department";
+    const TOPIC: Topic = Topic::Department;
+    const FILTERABLE: &'static [department::Column] = &[
+        department::Column::Id,
+        department::Column::Name,
+        department::Column::OfficeRoom,
+        department::Column::HomePage,
+    ];
+    const SORTABLE: &'static [department::Column] =
+        &[department::Column::Id, department::Column::Name];
+
+    type Key = String;
+    type Insert = InsertParams;
+    type Update = InsertParams;
+    type Patch = PatchParams;
+    type Filter = QueryParams;
+
+    fn filter(select: Select<Self>, params: QueryParams) -> Select<Self> {
+        select
+            .apply_if(params.keyword.as_ref(), |rows, keyword| {
+                rows.filter(department::Column::Name.contains(keyword))
+            })
+            .apply_if(params.office_room.as_ref(), |rows, keyword| {
+                rows.filter(department::Column::OfficeRoom.eq(keyword))
+            })
+            .apply_if(params.home_page.as_ref(), |rows, keyword| {
+                rows.filter(department::Column::HomePage.eq(keyword))
+            })
+    }
 }
 
 /// 对 department 进行更改所需的参数
-#[derive(Deserialize, Validate, DeriveIntoActiveModel)]
-struct InsertParams {
+#[derive(Deserialize, Validate, DeriveIntoActiveModel, JsonSchema, InputObject)]
+#[schemars(rename = "DepartmentInsertParams")]
+#[graphql(name = "DepartmentInput")]
+pub struct InsertParams {
     #[validate(length(min = 1, max = 2))]
     id: String,
 
@@ -47,56 +81,46 @@ struct InsertParams {
     home_page: Option<String>,
 }
 
-/// 路由到 department 模块下的 insert 界面
-#[debug_handler]
-async fn insert(
-    State(state): State<ServerState>,
-    ValidJson(params): ValidJson<InsertParams>,
-) -> AppResult<String> {
-    tracing::debug!("开始处理: 添加 Department");
-    throw_err!(params.into_active_model().insert(state.db()).await);
-    AppResult::Ok("成功添加一条 Department 记录!".to_string())
+impl Importable for InsertParams {
+    async fn import(self, txn: &DatabaseTransaction, usr: &UserIdent) -> Result<(), AppError> {
+        let created = self.into_active_model().insert(txn).await?;
+        events::publish(txn, Topic::Department, Action::Insert, usr, &created).await?;
+        Ok(())
+    }
 }
 
-/// 路由到 department 模块下的 update 界面
-#[debug_handler]
-async fn update(
-    State(state): State<ServerState>,
-    Path(id): Path<String>,
-    ValidJson(params): ValidJson<InsertParams>,
-) -> AppResult<String> {
-    tracing::debug!("开始处理: 修改 Department");
-    let target = throw_err!(Department::find_by_id(&id).one(state.db()).await);
-    if target.is_some() {
-        throw_err!(params.into_active_model().update(state.db()).await);
-        AppResult::Ok("成功修改一条 Department 数据!".to_string())
-    } else {
-        AppResult::Err(AppError::NotFound(
-            "没有相关的 Department 记录!".to_string(),
-        ))
-    }
+/// 部分更新 department 所需的参数, 只有请求中出现了的字段会被更新, 显式地传 null 会把可以为空的字段置空
+#[derive(Deserialize, Validate, JsonSchema)]
+#[schemars(rename = "DepartmentPatchParams")]
+pub struct PatchParams {
+    #[validate(length(min = 1, max = 20))]
+    #[serde(default, deserialize_with = "patch::nullable")]
+    name: Option<Option<String>>,
+
+    #[validate(length(max = 40))]
+    #[serde(default, deserialize_with = "patch::nullable")]
+    office_room: Option<Option<String>>,
+
+    #[validate(length(max = 80))]
+    #[serde(default, deserialize_with = "patch::nullable")]
+    home_page: Option<Option<String>>,
 }
 
-/// 路由到 department 模块下的 delete 页面
-#[debug_handler]
-async fn delete(State(state): State<ServerState>, Path(id): Path<String>) -> AppResult<String> {
-    tracing::debug!("开始处理: 删除 Department");
-    let target = throw_err!(Department::find_by_id(&id).one(state.db()).await);
-    if let Some(department) = target {
-        throw_err!(department.delete(state.db()).await);
-        tracing::info!("已删除一条 id 为 {id} 的 Department 记录");
-        AppResult::Ok(format!("成功删除一条 id 为 {id} 的 Department 记录!"))
-    } else {
-        AppResult::Err(AppError::NotFound(
-            "没有相关的 Department 记录!".to_string(),
-        ))
+impl IntoActiveModel<ActiveModel> for PatchParams {
+    fn into_active_model(self) -> ActiveModel {
+        ActiveModel {
+            name: patch::present(self.name),
+            office_room: patch::present(self.office_room),
+            home_page: patch::present(self.home_page),
+            ..Default::default()
+        }
     }
 }
 
 /// 路由到 department 模块下的 query 板块时的所需的参数
-#[derive(Deserialize, Validate)]
+#[derive(Deserialize, Validate, JsonSchema)]
 #[serde(rename_all = "camelCase")]
-struct QueryParams {
+pub struct QueryParams {
     keyword: Option<String>,
 
     #[validate(length(max = 40))]
@@ -104,38 +128,4 @@ struct QueryParams {
 
     #[validate(length(max = 80))]
     home_page: Option<String>,
-
-    #[validate(nested)]
-    #[serde(flatten)]
-    page: PageParam,
-}
-
-/// 处理路由到 department 模块下的查询请求
-#[debug_handler]
-async fn query(
-    State(state): State<ServerState>,
-    ValidQuery(params): ValidQuery<QueryParams>,
-) -> AppResult<Page<Model>> {
-    tracing::debug!("开始处理: 查询 Department");
-    let pagination = Department::find()
-        .apply_if(params.keyword.as_ref(), |rows, keyword| {
-            rows.filter(department::Column::Name.contains(keyword))
-        })
-        .apply_if(params.office_room.as_ref(), |rows, keyword| {
-            rows.filter(department::Column::OfficeRoom.eq(keyword))
-        })
-        .apply_if(params.home_page.as_ref(), |rows, keyword| {
-            rows.filter(department::Column::HomePage.eq(keyword))
-        })
-        .order_by_asc(department::Column::Id)
-        .paginate(state.db(), params.page.size);
-
-    let total = throw_err!(pagination.num_pages().await);
-    let items = throw_err!(pagination.fetch_page(params.page.index - 1).await);
-
-    AppResult::Ok(Page {
-        param: params.page,
-        total,
-        items,
-    })
 }
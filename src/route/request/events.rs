@@ -0,0 +1,341 @@
+use crate::comma_list;
+use crate::error::AppError;
+use crate::i18n;
+use crate::route::extract::ValidQuery;
+use crate::route::middleware;
+use crate::route::openapi::{ApiDoc, Operation};
+use crate::route::request::login::UserIdent;
+use crate::route::routes::Routes;
+use crate::server::ServerState;
+use crate::t;
+use axum::extract::State;
+use axum::extract::ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code};
+use axum::http::{HeaderMap, header};
+use axum::response::sse::{self, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use schemars::JsonSchema;
+use sea_orm::sqlx::postgres::PgListener;
+use sea_orm::{ConnectionTrait, DbBackend, DbErr, Statement};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Instant;
+use tokio_stream::StreamExt;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use validator::Validate;
+
+/// 数据变更通知所用的 PostgreSQL 频道
+const CHANNEL: &str = "web_start_events";
+
+/// 每个服务器实例中转发给订阅者的事件缓冲区大小, 订阅者落后太多时会收到 `lagged`
+pub const CAPACITY: usize = 1024;
+
+/// 不在认证层之内, 因为浏览器的 EventSource 和 WebSocket 不能设置请求头, 认证在处理函数中完成
+pub fn router() -> Routes {
+    Routes::new().get("/", stream).get("/ws", socket)
+}
+
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    doc.get(
+        "/",
+        Operation::new("通过 Server-Sent Events 订阅数据变更")
+            .description(
+                "每一条事件的 `event` 为 insert, update 或 delete, `data` 为 `Event`. \
+                 落后太多时会收到一条 `lagged` 事件, 其中是被跳过的事件数量, 此时应当重新拉取数据. \
+                 token 过期时服务器会结束这个流, 需要用新的 token 重新订阅.",
+            )
+            .query::<EventsParams>()
+            .returns_content("text/event-stream"),
+    )
+    .get(
+        "/ws",
+        Operation::new("通过 WebSocket 订阅数据变更")
+            .description(
+                "升级为 WebSocket 之后, 服务器发送的每一条文本消息都是一个 `Event`, 落后太多时为 `{\"lagged\": 跳过的数量}`. \
+                 客户端可以随时发送 `{\"entity\": \"score,student\"}` 来修改订阅的实体. \
+                 token 过期时服务器会以 1008 关闭连接, 需要用新的 token 重新连接.",
+            )
+            .query::<EventsParams>()
+            .returns::<Event>(),
+    );
+}
+
+/// 会发布变更通知的实体, 序列化后为表名
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Topic {
+    Student,
+    Course,
+    Department,
+    Score,
+    Term,
+    ScoreHistory,
+}
+
+/// 变更的类型, 也是 SSE 中的事件名
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Insert => "insert",
+            Action::Update => "update",
+            Action::Delete => "delete",
+        }
+    }
+}
+
+/// 一条数据变更的通知
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug)]
+pub struct Event {
+    pub entity: Topic,
+    pub action: Action,
+    /// 进行这次操作的用户
+    pub user: String,
+    /// 变更之后的记录, 删除时为被删除的记录
+    pub data: serde_json::Value,
+}
+
+/// 在 `db` 上发出一条变更通知
+///
+/// 在事务中调用时, 通知会在事务提交之后才被发出, 回滚时则不会发出, 所以订阅者只会看到真正生效的变更
+pub async fn publish<C: ConnectionTrait>(
+    db: &C,
+    entity: Topic,
+    action: Action,
+    usr: &UserIdent,
+    data: &impl Serialize,
+) -> Result<(), DbErr> {
+    let event = Event {
+        entity,
+        action,
+        user: usr.id.clone(),
+        data: serde_json::to_value(data).map_err(|e| DbErr::Json(e.to_string()))?,
+    };
+    let payload = serde_json::to_string(&event).map_err(|e| DbErr::Json(e.to_string()))?;
+    db.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "SELECT pg_notify($1, $2)",
+        [CHANNEL.into(), payload.into()],
+    ))
+    .await?;
+    Ok(())
+}
+
+/// 监听 PostgreSQL 中的变更通知, 并转发给这个实例上的所有订阅者
+///
+/// 所有实例都监听同一个频道, 所以无论变更发生在哪个实例上, 订阅者都能收到. 连接出错时每 5 秒重试一次
+pub async fn listen(state: ServerState) {
+    loop {
+        if let Err(e) = forward(&state).await {
+            tracing::error!("监听数据变更通知时出错, 5 秒后重试, 详细信息: {e}");
+        }
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+async fn forward(state: &ServerState) -> Result<(), sea_orm::sqlx::Error> {
+    let mut listener = PgListener::connect_with(state.db().get_postgres_connection_pool()).await?;
+    listener.listen(CHANNEL).await?;
+    tracing::info!("开始监听频道 {CHANNEL} 中的数据变更通知.");
+
+    loop {
+        let notification = listener.recv().await?;
+        match serde_json::from_str::<Event>(notification.payload()) {
+            // 没有订阅者时发送会失败, 直接丢弃即可
+            Ok(event) => _ = state.events().send(event),
+            Err(e) => tracing::warn!("无法解析数据变更通知, 详细信息: {e}"),
+        }
+    }
+}
+
+/// `entity` 参数, 形如 `entity=score,student`, 不填时订阅全部实体
+#[derive(Default, Debug, PartialEq)]
+struct Topics(Vec<Topic>);
+
+impl Topics {
+    fn accepts(&self, event: &Event) -> bool {
+        self.0.is_empty() || self.0.contains(&event.entity)
+    }
+}
+
+comma_list!(Topics, "score,student");
+
+/// 订阅时的参数
+#[derive(Deserialize, Validate, JsonSchema)]
+struct EventsParams {
+    #[serde(default)]
+    entity: Topics,
+
+    /// 不能设置 `Authorization` 请求头时 (如浏览器中的 EventSource 和 WebSocket), 可以通过这个参数传递 token
+    access_token: Option<String>,
+}
+
+/// 和其他接口一样校验 JWT, 优先使用 `Authorization` 请求头, 返回用户和 token 过期的时刻
+fn authenticate(
+    headers: &HeaderMap,
+    params: &EventsParams,
+) -> Result<(UserIdent, Instant), AppError> {
+    let (usr, expires_at) = match headers.get(header::AUTHORIZATION) {
+        Some(auth_header) => {
+            let auth_header = auth_header
+                .to_str()
+                .map_err(|e| AppError::BadRequest(t!("auth.bad_encoding", error = e)))?;
+            middleware::authenticate_until(Some(auth_header))?
+        }
+        None => {
+            let auth_header = params.access_token.as_ref().map(|t| format!("Bearer {t}"));
+            middleware::authenticate_until(auth_header.as_deref())?
+        }
+    };
+    let remaining = expires_at
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+    Ok((usr, Instant::now() + remaining))
+}
+
+/// 通过 Server-Sent Events 推送订阅的实体的变更
+async fn stream(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    ValidQuery(params): ValidQuery<EventsParams>,
+) -> Response {
+    let (usr, deadline) = match authenticate(&headers, &params) {
+        Ok(authenticated) => authenticated,
+        Err(e) => return e.into_response(),
+    };
+    tracing::debug!("{} 开始通过 SSE 订阅数据变更", usr.id);
+
+    let topics = params.entity;
+    let events = BroadcastStream::new(state.events().subscribe()).filter_map(move |event| {
+        let event = match event {
+            Ok(event) if topics.accepts(&event) => sse::Event::default()
+                .event(event.action.as_str())
+                .json_data(&event)
+                .ok()?,
+            Ok(_) => return None,
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => sse::Event::default()
+                .event("lagged")
+                .data(skipped.to_string()),
+        };
+        Some(Some(Ok::<_, Infallible>(event)))
+    });
+    // token 过期时结束推送, 客户端需要用新的 token 重新订阅
+    let expiry = tokio_stream::once(deadline).then(|deadline| async move {
+        tokio::time::sleep_until(deadline).await;
+        None
+    });
+    Sse::new(events.merge(expiry).map_while(|event| event))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// 升级为 WebSocket 连接, 推送订阅的实体的变更
+async fn socket(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    ValidQuery(params): ValidQuery<EventsParams>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let (usr, deadline) = match authenticate(&headers, &params) {
+        Ok(authenticated) => authenticated,
+        Err(e) => return e.into_response(),
+    };
+    tracing::debug!("{} 开始通过 WebSocket 订阅数据变更", usr.id);
+
+    // 连接建立之后不再处于这个请求中, 错误消息沿用这个请求的语言
+    let lang = i18n::current();
+    upgrade.on_upgrade(move |socket| {
+        i18n::scope(lang, subscribe(socket, state, params.entity, deadline))
+    })
+}
+
+/// 客户端发来的消息, 用于修改订阅的实体
+#[derive(Deserialize)]
+struct Subscribe {
+    #[serde(default)]
+    entity: Topics,
+}
+
+/// 推送订阅的实体的变更, 直到客户端断开或者 token 在 `deadline` 过期
+async fn subscribe(
+    mut socket: WebSocket,
+    state: ServerState,
+    mut topics: Topics,
+    deadline: Instant,
+) {
+    let mut events = state.events().subscribe();
+    loop {
+        let reply = tokio::select! {
+            _ = tokio::time::sleep_until(deadline) => {
+                let frame = CloseFrame {
+                    code: close_code::POLICY,
+                    reason: t!("auth.expired").into(),
+                };
+                _ = socket.send(Message::Close(Some(frame))).await;
+                break;
+            }
+            event = events.recv() => match event {
+                Ok(event) if topics.accepts(&event) => serde_json::to_string(&event).ok(),
+                Ok(_) => None,
+                Err(RecvError::Lagged(skipped)) => {
+                    Some(serde_json::json!({ "lagged": skipped }).to_string())
+                }
+                Err(RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<Subscribe>(&text) {
+                    Ok(subscribe) => {
+                        topics = subscribe.entity;
+                        None
+                    }
+                    Err(e) => {
+                        let e = AppError::BadJson(e.to_string());
+                        let reply = serde_json::json!({
+                            "status_code": e.status_code().as_u16(),
+                            "code": e.code(),
+                            "message": e.to_string(),
+                        });
+                        Some(reply.to_string())
+                    }
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => None,
+            },
+        };
+        if let Some(reply) = reply
+            && socket.send(Message::Text(reply.into())).await.is_err()
+        {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_topics() {
+        let params: Subscribe =
+            serde_json::from_str(r#"{"entity": "score, student,score"}"#).unwrap();
+        assert_eq!(params.entity, Topics(vec![Topic::Score, Topic::Student]));
+        assert!(serde_json::from_str::<Subscribe>(r#"{"entity": "users"}"#).is_err());
+
+        let event: Event = serde_json::from_str(
+            r#"{"entity": "score_history", "action": "insert", "user": "admin", "data": {}}"#,
+        )
+        .unwrap();
+        assert!(!params.entity.accepts(&event));
+        assert!(Topics::default().accepts(&event));
+    }
+}
@@ -0,0 +1,712 @@
+use crate::entity::prelude::{Course, Department, Score, Student};
+use crate::entity::{course, department, score, student};
+use crate::error::AppError;
+use crate::route::crud::{self, CrudResource};
+use crate::route::etag::{self, IfMatch};
+use crate::route::filter::Filters;
+use crate::route::openapi::{ApiDoc, Operation};
+use crate::route::page::PageParam;
+use crate::route::request::login::UserIdent;
+use crate::route::routes::Routes;
+use crate::route::sort::SortParam;
+use crate::server::ServerState;
+use crate::t;
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{
+    ComplexObject, Context, EmptySubscription, ErrorExtensions, InputObject, Object, OutputType,
+    Schema, SimpleObject,
+};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::extract::State;
+use axum::{Extension, debug_handler};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel, Iterable,
+    PrimaryKeyToColumn, QueryFilter, QueryOrder,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+use validator::Validate;
+
+pub fn router() -> Routes {
+    Routes::new().post("/", graphql)
+}
+
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    doc.post(
+        "/",
+        Operation::new("GraphQL 查询")
+            .description(
+                "可以查询和修改 student, course, department 和 score, 并通过它们之间的关系一次取出关联数据. \
+                 请求体为 `{\"query\": ..., \"variables\": ...}`, 筛选, 排序, 分页以及写操作的校验和权限都和 REST 接口相同. \
+                 修改和删除需要在 `etag` 参数中带上读取时得到的 `etag` 字段, 作用和 REST 接口中的 If-Match 相同.",
+            )
+            .body(&["application/json"]),
+    );
+}
+
+type AppSchema = Schema<Query, Mutation, EmptySubscription>;
+
+/// GraphQL 的 schema 不包含任何状态, 数据库连接, 当前用户和 DataLoader 在每个请求中单独传入
+static SCHEMA: LazyLock<AppSchema> = LazyLock::new(|| {
+    Schema::build(Query, Mutation, EmptySubscription)
+        .limit_depth(10)
+        .finish()
+});
+
+/// 执行一个 GraphQL 请求, 每个请求使用一个新的 DataLoader, 因此缓存不会跨请求共享
+#[debug_handler]
+async fn graphql(
+    State(state): State<ServerState>,
+    Extension(usr): Extension<UserIdent>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    tracing::debug!("开始处理: GraphQL 请求");
+    let db = state.db().clone();
+    let loader = DataLoader::new(DbLoader(db.clone()), tokio::spawn);
+    let request = request.into_inner().data(db).data(usr).data(loader);
+    SCHEMA.execute(request).await.into()
+}
+
+impl ErrorExtensions for AppError {
+    /// 错误的 `extensions` 中带有和 REST 接口相同的 `status_code` 和 `code`
+    fn extend(&self) -> async_graphql::Error {
+        async_graphql::Error::new(self.to_string()).extend_with(|_, e| {
+            e.set("status_code", self.status_code().as_u16());
+            e.set("code", self.code());
+        })
+    }
+}
+
+type GqlResult<T> = async_graphql::Result<T>;
+
+fn db<'a>(ctx: &Context<'a>) -> &'a DatabaseConnection {
+    ctx.data_unchecked()
+}
+
+fn usr<'a>(ctx: &Context<'a>) -> &'a UserIdent {
+    ctx.data_unchecked()
+}
+
+/// 一个筛选条件, 和 REST 接口中的 `filter[field][op]=value` 相同
+#[derive(InputObject)]
+struct FilterInput {
+    field: String,
+    /// 省略时为 eq
+    op: Option<String>,
+    value: String,
+}
+
+/// 一页数据
+#[derive(SimpleObject)]
+#[graphql(concrete(name = "StudentPage", params(student::Model)))]
+#[graphql(concrete(name = "CoursePage", params(course::Model)))]
+#[graphql(concrete(name = "DepartmentPage", params(department::Model)))]
+#[graphql(concrete(name = "ScorePage", params(score::Model)))]
+struct PageOf<T: OutputType> {
+    index: u64,
+    size: u64,
+    total_items: u64,
+    total_pages: u64,
+    items: Vec<T>,
+}
+
+/// 列表查询的参数
+struct ListArgs {
+    filter: Option<Vec<FilterInput>>,
+    sort: Option<String>,
+    index: Option<u64>,
+    size: Option<u64>,
+}
+
+/// 和 REST 的 query 接口使用同一套筛选, 排序和分页的规则
+async fn list<R: CrudResource>(ctx: &Context<'_>, args: ListArgs) -> GqlResult<PageOf<R::Model>>
+where
+    R::Model: OutputType,
+{
+    let filters = Filters::new(args.filter.unwrap_or_default().into_iter().map(|f| {
+        let op = f.op.unwrap_or_else(|| "eq".to_string());
+        (f.field, op, f.value)
+    }));
+    let sort = SortParam::parse(args.sort.as_deref().unwrap_or_default());
+    let default = PageParam::default();
+    let param = PageParam {
+        index: args.index.unwrap_or(default.index),
+        size: args.size.unwrap_or(default.size),
+    };
+
+    let page = crud::search::<R>(db(ctx), filters, sort, param)
+        .await
+        .map_err(|e| e.extend())?;
+    Ok(PageOf {
+        index: page.param.index,
+        size: page.param.size,
+        total_items: page.total_items,
+        total_pages: page.total_pages,
+        items: page.items,
+    })
+}
+
+/// 和 REST 接口中的 ValidJson 一样, 校验失败时返回 422
+fn validate(params: &impl Validate) -> GqlResult<()> {
+    crud::validate(params).map_err(|e| e.extend())
+}
+
+/// 和 insert 接口走同一套校验, 权限检查和插入逻辑
+async fn create<R: CrudResource>(ctx: &Context<'_>, input: R::Insert) -> GqlResult<String> {
+    validate(&input)?;
+    crud::create::<R>(db(ctx), usr(ctx), input)
+        .await
+        .map_err(|e| e.extend())?;
+    Ok(t!("crud.inserted", entity = R::NAME))
+}
+
+/// `etag` 参数和 REST 接口中的 If-Match 请求头相同, 为空时返回 428, 和当前记录不符时返回 412
+fn if_match(etag: &str) -> GqlResult<IfMatch> {
+    IfMatch::from_etag(etag).map_err(|e| e.extend())
+}
+
+/// 记录的 `etag` 字段, 和按主键获取它时响应中的 ETag 相同
+fn etag_of(model: &impl serde::Serialize) -> GqlResult<String> {
+    etag::of(model).map_err(|e| e.extend())
+}
+
+/// 和 PUT 更新接口走同一套校验, 权限检查, 版本检查和更新前后的检查
+async fn update<R: CrudResource>(
+    ctx: &Context<'_>,
+    key: R::Key,
+    etag: String,
+    input: R::Update,
+) -> GqlResult<R::Model> {
+    validate(&input)?;
+    let if_match = if_match(&etag)?;
+    crud::save::<R>(db(ctx), usr(ctx), key, input.into_active_model(), &if_match)
+        .await
+        .map_err(|e| e.extend())
+}
+
+/// 和 DELETE 接口一样, 删除之前检查 `etag`
+async fn delete<R: CrudResource>(
+    ctx: &Context<'_>,
+    key: R::Key,
+    etag: String,
+) -> GqlResult<String> {
+    let if_match = if_match(&etag)?;
+    crud::remove::<R>(db(ctx), usr(ctx), key, &if_match)
+        .await
+        .map_err(|e| e.extend())?;
+    Ok(t!("crud.deleted", entity = R::NAME))
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// 分页查询学生, 筛选和排序的字段和 `/api/student/query` 相同
+    async fn students(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<Vec<FilterInput>>,
+        sort: Option<String>,
+        index: Option<u64>,
+        size: Option<u64>,
+    ) -> GqlResult<PageOf<student::Model>> {
+        let args = ListArgs {
+            filter,
+            sort,
+            index,
+            size,
+        };
+        list::<Student>(ctx, args).await
+    }
+
+    async fn student(&self, ctx: &Context<'_>, id: String) -> GqlResult<Option<student::Model>> {
+        load(ctx, StudentKey(id)).await
+    }
+
+    /// 分页查询课程, 筛选和排序的字段和 `/api/course/query` 相同
+    async fn courses(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<Vec<FilterInput>>,
+        sort: Option<String>,
+        index: Option<u64>,
+        size: Option<u64>,
+    ) -> GqlResult<PageOf<course::Model>> {
+        let args = ListArgs {
+            filter,
+            sort,
+            index,
+            size,
+        };
+        list::<Course>(ctx, args).await
+    }
+
+    async fn course(&self, ctx: &Context<'_>, id: String) -> GqlResult<Option<course::Model>> {
+        load(ctx, CourseKey(id)).await
+    }
+
+    /// 分页查询学院, 筛选和排序的字段和 `/api/department/query` 相同
+    async fn departments(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<Vec<FilterInput>>,
+        sort: Option<String>,
+        index: Option<u64>,
+        size: Option<u64>,
+    ) -> GqlResult<PageOf<department::Model>> {
+        let args = ListArgs {
+            filter,
+            sort,
+            index,
+            size,
+        };
+        list::<Department>(ctx, args).await
+    }
+
+    async fn department(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+    ) -> GqlResult<Option<department::Model>> {
+        load(ctx, DepartmentKey(id)).await
+    }
+
+    /// 分页查询成绩, 筛选和排序的字段和 `/api/score/query` 相同
+    async fn scores(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<Vec<FilterInput>>,
+        sort: Option<String>,
+        index: Option<u64>,
+        size: Option<u64>,
+    ) -> GqlResult<PageOf<score::Model>> {
+        let args = ListArgs {
+            filter,
+            sort,
+            index,
+            size,
+        };
+        list::<Score>(ctx, args).await
+    }
+
+    async fn score(
+        &self,
+        ctx: &Context<'_>,
+        stu_id: String,
+        course_id: String,
+    ) -> GqlResult<Option<score::Model>> {
+        let score = Score::find_by_id((stu_id, course_id)).one(db(ctx)).await;
+        score.map_err(|e| AppError::from(e).extend())
+    }
+}
+
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    async fn create_student(
+        &self,
+        ctx: &Context<'_>,
+        input: <Student as CrudResource>::Insert,
+    ) -> GqlResult<String> {
+        create::<Student>(ctx, input).await
+    }
+
+    async fn update_student(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        etag: String,
+        input: <Student as CrudResource>::Update,
+    ) -> GqlResult<student::Model> {
+        update::<Student>(ctx, id, etag, input).await
+    }
+
+    async fn delete_student(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        etag: String,
+    ) -> GqlResult<String> {
+        delete::<Student>(ctx, id, etag).await
+    }
+
+    async fn create_course(
+        &self,
+        ctx: &Context<'_>,
+        input: <Course as CrudResource>::Insert,
+    ) -> GqlResult<String> {
+        create::<Course>(ctx, input).await
+    }
+
+    async fn update_course(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        etag: String,
+        input: <Course as CrudResource>::Update,
+    ) -> GqlResult<course::Model> {
+        update::<Course>(ctx, id, etag, input).await
+    }
+
+    async fn delete_course(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        etag: String,
+    ) -> GqlResult<String> {
+        delete::<Course>(ctx, id, etag).await
+    }
+
+    async fn create_department(
+        &self,
+        ctx: &Context<'_>,
+        input: <Department as CrudResource>::Insert,
+    ) -> GqlResult<String> {
+        create::<Department>(ctx, input).await
+    }
+
+    async fn update_department(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        etag: String,
+        input: <Department as CrudResource>::Update,
+    ) -> GqlResult<department::Model> {
+        update::<Department>(ctx, id, etag, input).await
+    }
+
+    async fn delete_department(
+        &self,
+        ctx: &Context<'_>,
+        id: String,
+        etag: String,
+    ) -> GqlResult<String> {
+        delete::<Department>(ctx, id, etag).await
+    }
+
+    /// 和 `/api/score/insert` 一样, 关闭的学期不能再录入成绩, 并且会留下一条成绩历史
+    async fn create_score(
+        &self,
+        ctx: &Context<'_>,
+        input: <Score as CrudResource>::Insert,
+    ) -> GqlResult<String> {
+        create::<Score>(ctx, input).await
+    }
+
+    async fn update_score(
+        &self,
+        ctx: &Context<'_>,
+        stu_id: String,
+        course_id: String,
+        etag: String,
+        input: <Score as CrudResource>::Update,
+    ) -> GqlResult<score::Model> {
+        update::<Score>(ctx, (stu_id, course_id), etag, input).await
+    }
+
+    async fn delete_score(
+        &self,
+        ctx: &Context<'_>,
+        stu_id: String,
+        course_id: String,
+        etag: String,
+    ) -> GqlResult<String> {
+        delete::<Score>(ctx, (stu_id, course_id), etag).await
+    }
+}
+
+#[ComplexObject]
+impl student::Model {
+    /// 修改和删除时需要带上的 etag
+    async fn etag(&self) -> GqlResult<String> {
+        etag_of(self)
+    }
+
+    /// 学生所在的学院
+    async fn department(&self, ctx: &Context<'_>) -> GqlResult<Option<department::Model>> {
+        match &self.department_id {
+            Some(id) => load(ctx, DepartmentKey(id.clone())).await,
+            None => Ok(None),
+        }
+    }
+
+    /// 学生的所有成绩
+    async fn scores(&self, ctx: &Context<'_>) -> GqlResult<Vec<score::Model>> {
+        let scores = load(ctx, ScoresOfStudent(self.id.clone())).await?;
+        Ok(scores.unwrap_or_default())
+    }
+}
+
+#[ComplexObject]
+impl course::Model {
+    /// 修改和删除时需要带上的 etag
+    async fn etag(&self) -> GqlResult<String> {
+        etag_of(self)
+    }
+
+    /// 开设课程的学院
+    async fn department(&self, ctx: &Context<'_>) -> GqlResult<Option<department::Model>> {
+        match &self.department_id {
+            Some(id) => load(ctx, DepartmentKey(id.clone())).await,
+            None => Ok(None),
+        }
+    }
+
+    /// 这门课程的所有成绩
+    async fn scores(&self, ctx: &Context<'_>) -> GqlResult<Vec<score::Model>> {
+        let scores = load(ctx, ScoresOfCourse(self.id.clone())).await?;
+        Ok(scores.unwrap_or_default())
+    }
+}
+
+#[ComplexObject]
+impl department::Model {
+    /// 修改和删除时需要带上的 etag
+    async fn etag(&self) -> GqlResult<String> {
+        etag_of(self)
+    }
+
+    /// 学院的所有学生
+    async fn students(&self, ctx: &Context<'_>) -> GqlResult<Vec<student::Model>> {
+        let students = load(ctx, StudentsOfDepartment(self.id.clone())).await?;
+        Ok(students.unwrap_or_default())
+    }
+
+    /// 学院开设的所有课程
+    async fn courses(&self, ctx: &Context<'_>) -> GqlResult<Vec<course::Model>> {
+        let courses = load(ctx, CoursesOfDepartment(self.id.clone())).await?;
+        Ok(courses.unwrap_or_default())
+    }
+}
+
+#[ComplexObject]
+impl score::Model {
+    /// 修改和删除时需要带上的 etag
+    async fn etag(&self) -> GqlResult<String> {
+        etag_of(self)
+    }
+
+    async fn student(&self, ctx: &Context<'_>) -> GqlResult<Option<student::Model>> {
+        load(ctx, StudentKey(self.stu_id.clone())).await
+    }
+
+    async fn course(&self, ctx: &Context<'_>) -> GqlResult<Option<course::Model>> {
+        load(ctx, CourseKey(self.course_id.clone())).await
+    }
+}
+
+/// 按关系批量加载关联数据, 同一个请求中同一种关系的所有键只会查询一次, 避免 N+1 查询
+struct DbLoader(DatabaseConnection);
+
+/// 通过当前请求的 DataLoader 加载 `key` 所对应的数据
+async fn load<K>(ctx: &Context<'_>, key: K) -> GqlResult<Option<<DbLoader as Loader<K>>::Value>>
+where
+    K: Send + Sync + std::hash::Hash + Eq + Clone + 'static,
+    DbLoader: Loader<K, Error = Arc<DbErr>>,
+{
+    let loader: &DataLoader<DbLoader> = ctx.data_unchecked();
+    loader
+        .load_one(key)
+        .await
+        .map_err(|e| AppError::Database(e.to_string()).extend())
+}
+
+/// 取出 `column` 的值属于 `keys` 的所有记录, 按 `key_of` 分组, 组内按主键排序
+async fn group_by<E: EntityTrait>(
+    db: &DatabaseConnection,
+    column: E::Column,
+    keys: impl Iterator<Item = String>,
+    key_of: fn(&E::Model) -> Option<&String>,
+) -> Result<HashMap<String, Vec<E::Model>>, Arc<DbErr>> {
+    let mut select = E::find().filter(column.is_in(keys));
+    for key in E::PrimaryKey::iter() {
+        select = select.order_by_asc(key.into_column());
+    }
+    let models = select.all(db).await.map_err(Arc::new)?;
+
+    let mut groups: HashMap<String, Vec<E::Model>> = HashMap::new();
+    for model in models {
+        if let Some(key) = key_of(&model) {
+            groups.entry(key.clone()).or_default().push(model);
+        }
+    }
+    Ok(groups)
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct StudentKey(String);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CourseKey(String);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct DepartmentKey(String);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ScoresOfStudent(String);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ScoresOfCourse(String);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct StudentsOfDepartment(String);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CoursesOfDepartment(String);
+
+impl Loader<StudentKey> for DbLoader {
+    type Value = student::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[StudentKey],
+    ) -> Result<HashMap<StudentKey, Self::Value>, Self::Error> {
+        let keys = keys.iter().map(|key| key.0.clone());
+        let groups =
+            group_by::<Student>(&self.0, student::Column::Id, keys, |m| Some(&m.id)).await?;
+        Ok(groups
+            .into_iter()
+            .filter_map(|(key, mut models)| Some((StudentKey(key), models.pop()?)))
+            .collect())
+    }
+}
+
+impl Loader<CourseKey> for DbLoader {
+    type Value = course::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[CourseKey],
+    ) -> Result<HashMap<CourseKey, Self::Value>, Self::Error> {
+        let keys = keys.iter().map(|key| key.0.clone());
+        let groups = group_by::<Course>(&self.0, course::Column::Id, keys, |m| Some(&m.id)).await?;
+        Ok(groups
+            .into_iter()
+            .filter_map(|(key, mut models)| Some((CourseKey(key), models.pop()?)))
+            .collect())
+    }
+}
+
+impl Loader<DepartmentKey> for DbLoader {
+    type Value = department::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[DepartmentKey],
+    ) -> Result<HashMap<DepartmentKey, Self::Value>, Self::Error> {
+        let keys = keys.iter().map(|key| key.0.clone());
+        let groups =
+            group_by::<Department>(&self.0, department::Column::Id, keys, |m| Some(&m.id)).await?;
+        Ok(groups
+            .into_iter()
+            .filter_map(|(key, mut models)| Some((DepartmentKey(key), models.pop()?)))
+            .collect())
+    }
+}
+
+impl Loader<ScoresOfStudent> for DbLoader {
+    type Value = Vec<score::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[ScoresOfStudent],
+    ) -> Result<HashMap<ScoresOfStudent, Self::Value>, Self::Error> {
+        let keys = keys.iter().map(|key| key.0.clone());
+        let groups =
+            group_by::<Score>(&self.0, score::Column::StuId, keys, |m| Some(&m.stu_id)).await?;
+        Ok(groups
+            .into_iter()
+            .map(|(key, models)| (ScoresOfStudent(key), models))
+            .collect())
+    }
+}
+
+impl Loader<ScoresOfCourse> for DbLoader {
+    type Value = Vec<score::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[ScoresOfCourse],
+    ) -> Result<HashMap<ScoresOfCourse, Self::Value>, Self::Error> {
+        let keys = keys.iter().map(|key| key.0.clone());
+        let groups = group_by::<Score>(&self.0, score::Column::CourseId, keys, |m| {
+            Some(&m.course_id)
+        })
+        .await?;
+        Ok(groups
+            .into_iter()
+            .map(|(key, models)| (ScoresOfCourse(key), models))
+            .collect())
+    }
+}
+
+impl Loader<StudentsOfDepartment> for DbLoader {
+    type Value = Vec<student::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[StudentsOfDepartment],
+    ) -> Result<HashMap<StudentsOfDepartment, Self::Value>, Self::Error> {
+        let keys = keys.iter().map(|key| key.0.clone());
+        let groups = group_by::<Student>(&self.0, student::Column::DepartmentId, keys, |m| {
+            m.department_id.as_ref()
+        })
+        .await?;
+        Ok(groups
+            .into_iter()
+            .map(|(key, models)| (StudentsOfDepartment(key), models))
+            .collect())
+    }
+}
+
+impl Loader<CoursesOfDepartment> for DbLoader {
+    type Value = Vec<course::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[CoursesOfDepartment],
+    ) -> Result<HashMap<CoursesOfDepartment, Self::Value>, Self::Error> {
+        let keys = keys.iter().map(|key| key.0.clone());
+        let groups = group_by::<Course>(&self.0, course::Column::DepartmentId, keys, |m| {
+            m.department_id.as_ref()
+        })
+        .await?;
+        Ok(groups
+            .into_iter()
+            .map(|(key, models)| (CoursesOfDepartment(key), models))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_schema() {
+        let sdl = SCHEMA.sdl();
+        assert!(sdl.contains("type Student"));
+        assert!(sdl.contains("department: Department"));
+        assert!(sdl.contains("createScore(input: ScoreInput!): String!"));
+        assert!(sdl.contains("deleteStudent(id: String!, etag: String!): String!"));
+        assert!(sdl.contains("etag: String!"));
+
+        // 筛选参数在查询数据库之前就会被校验, 错误中带有和 REST 接口相同的状态码
+        let query = r#"{ students(filter: [{field: "password", value: "x"}]) { totalItems } }"#;
+        let request = async_graphql::Request::new(query).data(DatabaseConnection::Disconnected);
+        let response = SCHEMA.execute(request).await;
+        let error = &response.errors[0];
+        assert!(error.message.contains("password"));
+        let status_code = error.extensions.as_ref().and_then(|e| e.get("status_code"));
+        assert_eq!(status_code, Some(&async_graphql::Value::from(400)));
+    }
+}
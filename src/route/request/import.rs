@@ -0,0 +1,345 @@
+use crate::entity::prelude::{Course, Department, Score, Student};
+use crate::error::AppError;
+use crate::route::crud;
+use crate::route::extract::{Path, ValidQuery};
+use crate::route::openapi::{ApiDoc, Operation};
+use crate::route::request::login::UserIdent;
+use crate::route::request::{course, department, score, student};
+use crate::route::result::AppResult;
+use crate::route::routes::Routes;
+use crate::server::ServerState;
+use crate::t;
+use crate::throw_err;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, header};
+use axum::{Extension, debug_handler};
+use calamine::{Data, Reader, Xlsx};
+use csv::StringRecord;
+use schemars::JsonSchema;
+use sea_orm::{DatabaseTransaction, TransactionTrait};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+use validator::Validate;
+
+pub fn router() -> Routes {
+    Routes::new().post("/{target}", import)
+}
+
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    doc.post(
+        "/{target}",
+        Operation::new("从 CSV 或 XLSX 文件导入数据")
+            .description("请求体为文件的内容, 没有指定 `format` 时根据 Content-Type 判断格式.")
+            .path::<ImportTarget>("target")
+            .query::<ImportParams>()
+            .body(&["text/csv", XLSX_MIME])
+            .returns::<ImportReport>(),
+    );
+}
+
+/// 可以通过表格导入的数据, 由各模块的 `InsertParams` 实现,
+/// 从而复用和 insert 接口完全相同的校验规则
+pub trait Importable: DeserializeOwned + Validate + Send {
+    /// 在给定的事务中插入这一行数据
+    fn import(
+        self,
+        txn: &DatabaseTransaction,
+        usr: &UserIdent,
+    ) -> impl Future<Output = Result<(), AppError>> + Send;
+}
+
+/// 导入的目标表
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ImportTarget {
+    Student,
+    Course,
+    Department,
+    Score,
+}
+
+/// 上传的文件格式
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum ImportFormat {
+    Csv,
+    Xlsx,
+}
+
+const XLSX_MIME: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+/// 导入时可以附带的请求参数
+#[derive(Deserialize, Validate, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct ImportParams {
+    /// 文件格式, 不填时根据 Content-Type 判断
+    format: Option<ImportFormat>,
+
+    /// 为 true 时只校验并报告错误, 不会写入任何数据
+    #[serde(default)]
+    dry_run: bool,
+
+    /// 每多少行提交一次事务
+    #[validate(range(min = 1, max = 5000, code = "batch_size"))]
+    #[serde(default = "ImportParams::default_batch_size")]
+    batch_size: usize,
+
+    /// 表头到字段名的映射, 形如 `学号=id,姓名=name`, 没有出现在这里的表头按原样作为字段名
+    mapping: Option<String>,
+}
+
+impl ImportParams {
+    const DEFAULT_BATCH_SIZE: usize = 500;
+
+    fn default_batch_size() -> usize {
+        ImportParams::DEFAULT_BATCH_SIZE
+    }
+
+    fn mapping(&self) -> Result<HashMap<&str, &str>, AppError> {
+        let Some(mapping) = self.mapping.as_deref() else {
+            return Ok(HashMap::new());
+        };
+
+        mapping
+            .split(',')
+            .filter(|pair| !pair.trim().is_empty())
+            .map(|pair| {
+                pair.split_once('=')
+                    .map(|(from, to)| (from.trim(), to.trim()))
+                    .ok_or_else(|| AppError::BadRequest(t!("import.bad_mapping", pair = pair)))
+            })
+            .collect()
+    }
+}
+
+/// 某一行导入失败的原因
+#[derive(Serialize, JsonSchema)]
+struct LineError {
+    /// 这一行在文件中的行号, 表头为第 1 行
+    line: u64,
+    error: String,
+}
+
+/// 导入的结果报告
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct ImportReport {
+    dry_run: bool,
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    /// 成功提交的批次数, dry run 时总为 0
+    batches_committed: usize,
+    errors: Vec<LineError>,
+}
+
+/// 将 CSV 或 XLSX 文件导入 `target` 表
+///
+/// 每一行都会在一个保存点中插入, 出错的行只回滚自己, 每 `batch_size` 行提交一次事务.
+/// dry run 时所有批次都在同一个事务中执行, 每个批次是其中的一个保存点, 最后整体回滚,
+/// 所以跨批次的主键冲突和对前面批次中数据的外键引用也能和真正导入时一样被正确地报告出来
+#[debug_handler]
+async fn import(
+    State(state): State<ServerState>,
+    Extension(usr): Extension<UserIdent>,
+    Path(target): Path<ImportTarget>,
+    ValidQuery(params): ValidQuery<ImportParams>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<ImportReport> {
+    tracing::debug!("开始处理: 导入表格");
+    // 导入和 insert 接口需要相同的权限, 在处理任何一行之前检查
+    throw_err!(match target {
+        ImportTarget::Student => crud::authorize::<Student>(&usr),
+        ImportTarget::Course => crud::authorize::<Course>(&usr),
+        ImportTarget::Department => crud::authorize::<Department>(&usr),
+        ImportTarget::Score => crud::authorize::<Score>(&usr),
+    });
+    let format = throw_err!(params.format.map_or_else(|| detect_format(&headers), Ok));
+    let mapping = throw_err!(params.mapping());
+    let (header, rows) = throw_err!(match format {
+        ImportFormat::Csv => read_csv(&body),
+        ImportFormat::Xlsx => read_xlsx(body),
+    });
+    let header = StringRecord::from_iter(
+        header
+            .iter()
+            .map(|name| mapping.get(name.trim()).copied().unwrap_or(name.trim())),
+    );
+
+    let report = match target {
+        ImportTarget::Student => {
+            import_rows::<student::InsertParams>(&state, &usr, &params, &header, rows).await
+        }
+        ImportTarget::Course => {
+            import_rows::<course::InsertParam>(&state, &usr, &params, &header, rows).await
+        }
+        ImportTarget::Department => {
+            import_rows::<department::InsertParams>(&state, &usr, &params, &header, rows).await
+        }
+        ImportTarget::Score => {
+            import_rows::<score::InsertParams>(&state, &usr, &params, &header, rows).await
+        }
+    };
+    let report = throw_err!(report);
+    tracing::info!(
+        "{} 导入了 {} 行数据, 成功 {} 行, 失败 {} 行, dry run: {}",
+        usr.id,
+        report.total,
+        report.succeeded,
+        report.failed,
+        report.dry_run
+    );
+    AppResult::Ok(report)
+}
+
+fn detect_format(headers: &HeaderMap) -> Result<ImportFormat, AppError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    if content_type.starts_with("text/csv") {
+        Ok(ImportFormat::Csv)
+    } else if content_type.starts_with(XLSX_MIME) {
+        Ok(ImportFormat::Xlsx)
+    } else {
+        Err(AppError::BadRequest(t!(
+            "import.unknown_format",
+            content_type = content_type,
+        )))
+    }
+}
+
+/// 读取 CSV 文件, 返回表头和带行号的数据行
+fn read_csv(body: &[u8]) -> Result<(StringRecord, Vec<(u64, StringRecord)>), AppError> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(body);
+    let header = reader
+        .headers()
+        .map_err(|e| AppError::BadRequest(t!("import.csv_header", error = e)))?
+        .clone();
+
+    let rows = reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| AppError::BadRequest(t!("import.csv", error = e)))?;
+            let line = record.position().map_or(0, |pos| pos.line());
+            Ok((line, record))
+        })
+        .collect::<Result<_, AppError>>()?;
+    Ok((header, rows))
+}
+
+/// 读取 XLSX 文件的第一个工作表, 每个单元格都会先被转换为字符串, 以便和 CSV 共用同一套反序列化流程
+fn read_xlsx(body: Bytes) -> Result<(StringRecord, Vec<(u64, StringRecord)>), AppError> {
+    let mut workbook = Xlsx::new(Cursor::new(body))
+        .map_err(|e| AppError::BadRequest(t!("import.xlsx", error = e)))?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| AppError::BadRequest(t!("import.xlsx_no_sheet")))?
+        .map_err(|e| AppError::BadRequest(t!("import.xlsx_sheet", error = e)))?;
+
+    let mut rows = range
+        .rows()
+        .map(|row| row.iter().map(cell_to_string).collect());
+    let header = rows
+        .next()
+        .ok_or_else(|| AppError::BadRequest(t!("import.xlsx_empty")))?;
+    let rows = rows
+        .enumerate()
+        .map(|(index, row)| (index as u64 + 2, row))
+        .filter(|(_, row): &(u64, StringRecord)| row.iter().any(|cell| !cell.is_empty()))
+        .collect();
+    Ok((header, rows))
+}
+
+fn cell_to_string(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::Int(i) => i.to_string(),
+        // 表格软件通常把整数也存成浮点数
+        Data::Float(f) if f.fract() == 0.0 => format!("{f:.0}"),
+        Data::Float(f) => f.to_string(),
+        Data::DateTime(dt) => dt
+            .as_datetime()
+            .map(|dt| dt.date().to_string())
+            .unwrap_or_else(|| dt.to_string()),
+        other => other.to_string(),
+    }
+}
+
+/// 将每一行反序列化为 `P`, 校验后分批插入
+async fn import_rows<P: Importable>(
+    state: &ServerState,
+    usr: &UserIdent,
+    params: &ImportParams,
+    header: &StringRecord,
+    rows: Vec<(u64, StringRecord)>,
+) -> Result<ImportReport, AppError> {
+    let total = rows.len();
+    let mut errors = Vec::new();
+    let mut batches_committed = 0;
+    let dry_run = if params.dry_run {
+        Some(state.db().begin().await?)
+    } else {
+        None
+    };
+
+    for batch in rows.chunks(params.batch_size) {
+        let txn = match &dry_run {
+            Some(outer) => outer.begin().await?,
+            None => state.db().begin().await?,
+        };
+        for (line, record) in batch {
+            if let Err(e) = import_row::<P>(&txn, usr, header, record).await {
+                errors.push(LineError {
+                    line: *line,
+                    error: e.to_string(),
+                });
+            }
+        }
+
+        // dry run 时只是释放这个批次的保存点, 数据仍然留在外层的事务中, 供后面的批次检查冲突
+        txn.commit().await?;
+        if dry_run.is_none() {
+            batches_committed += 1;
+        }
+    }
+    if let Some(outer) = dry_run {
+        outer.rollback().await?;
+    }
+
+    Ok(ImportReport {
+        dry_run: params.dry_run,
+        total,
+        succeeded: total - errors.len(),
+        failed: errors.len(),
+        batches_committed,
+        errors,
+    })
+}
+
+async fn import_row<P: Importable>(
+    txn: &DatabaseTransaction,
+    usr: &UserIdent,
+    header: &StringRecord,
+    record: &StringRecord,
+) -> Result<(), AppError> {
+    let row: P = record
+        .deserialize(Some(header))
+        .map_err(|e| AppError::UnprocessableEntity(e.to_string()))?;
+    row.validate().map_err(AppError::Validation)?;
+
+    let savepoint = txn.begin().await?;
+    match row.import(&savepoint, usr).await {
+        Ok(()) => Ok(savepoint.commit().await?),
+        Err(e) => {
+            savepoint.rollback().await?;
+            Err(e)
+        }
+    }
+}
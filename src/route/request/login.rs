@@ -4,36 +4,101 @@ use crate::error::AppError;
 use crate::route::extract::ValidJson;
 use crate::route::jwt::Jwt;
 use crate::route::middleware::AUTH_LAYER;
+use crate::route::openapi::{ApiDoc, Operation};
 use crate::route::result::AppResult;
+use crate::route::routes::Routes;
 use crate::server::ServerState;
+use crate::t;
 use crate::throw_err;
 use axum::extract::State;
-use axum::{Extension, Router, debug_handler, routing};
+use axum::{Extension, debug_handler};
+use schemars::JsonSchema;
 use sea_orm::EntityTrait;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-pub fn router() -> Router<ServerState> {
-    Router::new()
-        .route("/user-info", routing::get(info))
+pub fn router() -> Routes {
+    Routes::new()
+        .get("/user-info", info)
         .route_layer(&*AUTH_LAYER)
-        .route("/", routing::post(login))
+        .post("/", login)
+}
+
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    doc.get(
+        "/user-info",
+        Operation::new("获取当前登录的用户").returns::<Model>(),
+    )
+    .post(
+        "/",
+        Operation::new("登录")
+            .description("返回的 token 需要放在之后请求的 Authorization 请求头中.")
+            .public()
+            .error("401", "账号或者密码不正确")
+            .json::<Params>()
+            .returns::<String>(),
+    );
 }
 
 /// 登录参数
-#[derive(Deserialize, Validate)]
+#[derive(Deserialize, Validate, JsonSchema)]
+#[schemars(rename = "LoginParams")]
 struct Params {
-    #[validate(length(min = 1, max = 32, message = "id 长度应该小于 32 而大于 1"))]
+    #[validate(length(min = 1, max = 32, code = "login_id"))]
     id: String,
-    #[validate(length(min = 1, max = 128, message = "password 长度应该小于 128 而大于 1"))]
+    #[validate(length(min = 1, max = 128, code = "login_password"))]
     password: String,
 }
 
 /// 登陆完成后返回给浏览器的信息, 这将被存储在 jwt 中
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
 pub struct UserIdent {
     pub id: String,
     pub name: String,
+
+    /// 旧版本签发的 jwt 中没有这个字段, 视为权限最低的 [Role::Teacher]
+    #[serde(default)]
+    pub role: Role,
+}
+
+impl UserIdent {
+    /// 检查当前用户是否拥有 `roles` 中的任意一个角色, [Role::Admin] 总是可以通过检查
+    pub fn require(&self, roles: &[Role]) -> Result<(), AppError> {
+        if self.role == Role::Admin || roles.contains(&self.role) {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden(t!(
+                "auth.role_required",
+                roles = format!("{roles:?}"),
+                role = format!("{:?}", self.role),
+            )))
+        }
+    }
+}
+
+/// 用户的角色, 对应 users 表中的 role 字段
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// 任课教师, 可以录入成绩, 提交成绩修改申请
+    #[default]
+    Teacher,
+    /// 教务人员, 可以审批成绩修改申请
+    Registrar,
+    /// 管理员, 拥有所有权限
+    Admin,
+}
+
+impl From<&str> for Role {
+    /// 无法识别的角色一律视为 [Role::Teacher]
+    fn from(value: &str) -> Self {
+        match value {
+            "registrar" => Role::Registrar,
+            "admin" => Role::Admin,
+            _ => Role::Teacher,
+        }
+    }
 }
 
 #[debug_handler]
@@ -50,6 +115,7 @@ async fn login(
             if usr.password == param.password {
                 tracing::info!("登录成功!");
                 let usr_ident = UserIdent {
+                    role: Role::from(usr.role.as_str()),
                     id: usr.id,
                     name: usr.name,
                 };
@@ -63,7 +129,7 @@ async fn login(
             tracing::info!("此用户账号不存在!")
         }
     }
-    AppResult::Err(AppError::Unauthorized("账号或者密码不正确!".to_string()))
+    AppResult::Err(AppError::Unauthorized(t!("auth.bad_credentials")))
 }
 
 #[debug_handler]
@@ -79,3 +145,29 @@ async fn info(
 
     AppResult::Ok(entity)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ident(role: Role) -> UserIdent {
+        UserIdent {
+            id: "test".to_string(),
+            name: "test".to_string(),
+            role,
+        }
+    }
+
+    #[test]
+    fn test_role_require() {
+        assert!(ident(Role::Teacher).require(&[Role::Teacher]).is_ok());
+        assert!(ident(Role::Teacher).require(&[Role::Registrar]).is_err());
+        assert!(ident(Role::Admin).require(&[Role::Registrar]).is_ok());
+    }
+
+    #[test]
+    fn test_role_missing_in_old_jwt() {
+        let usr: UserIdent = serde_json::from_str(r#"{"id":"a","name":"b"}"#).unwrap();
+        assert_eq!(usr.role, Role::Teacher);
+    }
+}
@@ -1,31 +1,84 @@
 use crate::{
     error::AppError,
-    route::{middleware::AUTH_LAYER, result::AppResult},
+    route::{
+        middleware::AUTH_LAYER,
+        openapi::{self, ApiDoc},
+        result::AppResult,
+        routes::Routes,
+        version::{self, ApiVersion},
+    },
     server::ServerState,
 };
-use axum::{Router, debug_handler, handler::HandlerWithoutStateExt};
+use axum::{Router, debug_handler, handler::HandlerWithoutStateExt, middleware};
 use tower_http::services::ServeDir;
 
 pub mod course;
 pub mod department;
+pub mod events;
+pub mod graphql;
+pub mod import;
 pub mod login;
 pub mod score;
+pub mod score_history;
+pub mod search;
 pub mod student;
+pub mod term;
+pub mod transcript;
 
+/// 接口挂载在 `/api/v1`, `/api/v2` 等路径下, 没有版本号的 `/api` 是 v1 的别名
 pub fn build_router() -> Router<ServerState> {
-    let api = Router::new()
-        .nest("/student", student::router())
-        .nest("/score", score::router())
-        .nest("/department", department::router())
-        .nest("/course", course::router())
-        .route_layer(&*AUTH_LAYER)
-        .nest("/login", login::router());
-
     Router::new()
         .fallback_service(not_found.into_service())
         .method_not_allowed_fallback(not_allowed)
         .fallback_service(ServeDir::new("./static"))
-        .nest("/api", api)
+        .merge(routes().into_router())
+}
+
+/// [build_router] 中的所有接口
+pub fn routes() -> Routes {
+    ApiVersion::ALL.into_iter().fold(
+        Routes::new()
+            .merge(openapi::assets())
+            .nest("/api", api(ApiVersion::V1)),
+        |routes, version| routes.nest(&format!("/api{}", version.prefix()), api(version)),
+    )
+}
+
+/// `version` 版本的所有接口, 各版本共用同一套请求处理函数
+fn api(version: ApiVersion) -> Routes {
+    Routes::new()
+        .nest("/student", student::router(version))
+        .nest("/score", score::router(version))
+        .nest("/score-history", score_history::router())
+        .nest("/department", department::router(version))
+        .nest("/course", course::router(version))
+        .nest("/term", term::router(version))
+        .nest("/import", import::router())
+        .nest("/search", search::router())
+        .nest("/graphql", graphql::router())
+        .route_layer(&*AUTH_LAYER)
+        .nest("/login", login::router())
+        .nest("/verify", transcript::router())
+        .nest("/events", events::router())
+        .merge(openapi::router())
+        .layer(middleware::from_fn_with_state(version, version::scope))
+}
+
+/// 和 [build_router] 对应的接口文档, 新增路由时需要同时在这里加上文档, 否则测试会失败
+pub fn document(doc: &mut ApiDoc) {
+    doc.nest("/student", "student", student::document)
+        .nest("/score", "score", score::document)
+        .nest("/score-history", "score-history", score_history::document)
+        .nest("/department", "department", department::document)
+        .nest("/course", "course", course::document)
+        .nest("/term", "term", term::document)
+        .nest("/import", "import", import::document)
+        .nest("/search", "search", search::document)
+        .nest("/graphql", "graphql", graphql::document)
+        .nest("/login", "login", login::document)
+        .nest("/verify", "verify", transcript::document)
+        .nest("/events", "events", events::document)
+        .nest("", "openapi", openapi::document);
 }
 
 #[debug_handler]
@@ -1,40 +1,169 @@
-use crate::entity::prelude::Score;
+// DeriveIntoActiveModel 展开后总会带上 `..Default::default()`, InsertParams 的字段齐全时会触发这个 lint
+#![allow(clippy::needless_update)]
+
+use crate::entity::prelude::{Score, StudentScoreCourse};
 use crate::entity::score::{ActiveModel, Model};
-use crate::entity::{course, student};
+use crate::entity::student_score_course as detailed;
+use crate::entity::{course, score, student};
 use crate::error::AppError;
+use crate::route::crud::{self, CrudResource};
+use crate::route::export::{self, ExportFormat};
 use crate::route::extract::{ValidJson, ValidQuery};
+use crate::route::fields::{self, FieldsParam};
+use crate::route::filter::Filters;
+use crate::route::openapi::{ApiDoc, Operation};
 use crate::route::page::{Page, PageParam};
+use crate::route::patch;
+use crate::route::request::events::{self, Action, Topic};
+use crate::route::request::import::Importable;
+use crate::route::request::login::UserIdent;
+use crate::route::request::score_history;
 use crate::route::result::AppResult;
+use crate::route::routes::Routes;
+use crate::route::sort::SortParam;
+use crate::route::version::ApiVersion;
 use crate::server::ServerState;
+use crate::t;
 use crate::throw_err;
-use axum::extract::{Path, State};
-use axum::{Router, debug_handler, routing};
+use async_graphql::InputObject;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, debug_handler};
+use schemars::JsonSchema;
 use sea_orm::prelude::{Date, Expr};
 use sea_orm::sea_query::IntoCondition;
 use sea_orm::{
-    ActiveModelTrait, DeriveIntoActiveModel, EntityTrait, IntoActiveModel, JoinType, ModelTrait,
-    PaginatorTrait, QuerySelect, QueryTrait, RelationTrait,
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseTransaction, DeriveIntoActiveModel,
+    EntityTrait, IntoActiveModel, JoinType, QueryFilter, QuerySelect, QueryTrait, RelationTrait,
+    Select, TransactionTrait,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use validator::Validate;
 
-pub fn router() -> Router<ServerState> {
-    Router::new()
-        .route("/", routing::get(index))
-        .route("/query", routing::get(query))
-        .route("/insert", routing::post(insert))
-        .route("/update/{stu_id}/{course_id}", routing::put(update))
-        .route("/delete/{stu_id}/{course_id}", routing::delete(delete))
+pub fn router(version: ApiVersion) -> Routes {
+    crud::router::<Score>(version)
+        .get("/detailed", detailed)
+        .post("/bulk", bulk)
 }
 
-/// 路由到 score 模块下的默认界面
-#[debug_handler]
-async fn index() -> AppResult<&'static str> {
-    AppResult::Ok("欢迎!这是 Score 的首页!")
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    crud::document::<Score>(doc);
+    doc.get(
+        "/detailed",
+        Operation::new("查询成绩详情")
+            .description("结果中直接带有学生姓名和课程名称, 指定 `format` 参数或 Accept 请求头时导出全部结果.")
+            .query::<DetailedParams>()
+            .filter(DETAILED_COLUMNS)
+            .returns::<Page<detailed::Model>>(),
+    )
+    .post(
+        "/bulk",
+        Operation::new("为一门课程批量录入成绩")
+            .json::<BulkParams>()
+            .returns::<BulkReport>(),
+    );
 }
 
-#[derive(Validate, Deserialize, DeriveIntoActiveModel)]
-struct InsertParams {
+impl CrudResource for Score {
+    const NAME: &'static str = "Score";
+    const TOPIC: Topic = Topic::Score;
+    const KEY: &'static str = "{stu_id}/{course_id}";
+    const FILTERABLE: &'static [score::Column] = &[
+        score::Column::StuId,
+        score::Column::CourseId,
+        score::Column::Score,
+        score::Column::RecordDate,
+    ];
+    const SORTABLE: &'static [score::Column] = &[
+        score::Column::StuId,
+        score::Column::CourseId,
+        score::Column::Score,
+        score::Column::RecordDate,
+    ];
+
+    type Key = (String, String);
+    type Insert = InsertParams;
+    type Update = InsertParams;
+    type Patch = PatchParams;
+    type Filter = QueryParams;
+
+    fn filter(select: Select<Self>, params: QueryParams) -> Select<Self> {
+        select
+            .apply_if(params.student, |rows, keyword| {
+                rows.join(
+                    JoinType::InnerJoin,
+                    student::Relation::Score.def().rev().on_condition(
+                        move |_score, student_name| {
+                            Expr::col((student_name, student::Column::Name))
+                                .like(format!("%{keyword}%"))
+                                .into_condition()
+                        },
+                    ),
+                )
+            })
+            .apply_if(params.course, |rows, keyword| {
+                rows.join(
+                    JoinType::InnerJoin,
+                    course::Relation::Score
+                        .def()
+                        .rev()
+                        .on_condition(move |_score, course_name| {
+                            Expr::col((course_name, course::Column::Name))
+                                .like(format!("%{keyword}%"))
+                                .into_condition()
+                        }),
+                )
+            })
+    }
+
+    /// 学期关闭后成绩只能通过 score-history 模块的申请审批流程修改, 修改前后的日期所在的学期都不能是关闭的
+    async fn before_update(
+        txn: &DatabaseTransaction,
+        current: &Model,
+        changes: &ActiveModel,
+    ) -> Result<(), AppError> {
+        let new_date = changes.record_date.clone().unwrap();
+        for date in [current.record_date, new_date] {
+            if score_history::term_closed(txn, date).await? {
+                return Err(AppError::Forbidden(t!("score.term_closed_propose")));
+            }
+        }
+        Ok(())
+    }
+
+    /// 关闭的学期中的成绩不能删除, 否则删除之后可以用未关闭学期的日期重新录入, 从而绕过审批;
+    /// 每一次删除也会留下一条成绩历史
+    async fn before_delete(
+        txn: &DatabaseTransaction,
+        current: &Model,
+        usr: &UserIdent,
+    ) -> Result<(), AppError> {
+        if score_history::term_closed(txn, current.record_date).await? {
+            return Err(AppError::Forbidden(t!("score.term_closed_delete")));
+        }
+        score_history::record_delete(txn, current, usr).await?;
+        Ok(())
+    }
+
+    /// 每一次修改都会留下一条成绩历史
+    async fn after_update(
+        txn: &DatabaseTransaction,
+        old: &Model,
+        new: &Model,
+        usr: &UserIdent,
+    ) -> Result<(), AppError> {
+        score_history::record_direct(txn, Some(old), new, usr).await?;
+        Ok(())
+    }
+}
+
+#[derive(Validate, Deserialize, DeriveIntoActiveModel, JsonSchema, InputObject)]
+#[schemars(rename = "ScoreInsertParams")]
+#[graphql(name = "ScoreInput")]
+pub struct InsertParams {
     #[validate(length(max = 6))]
     stu_id: String,
 
@@ -45,108 +174,320 @@ struct InsertParams {
     record_date: Option<Date>,
 }
 
-#[debug_handler]
-async fn insert(
-    State(state): State<ServerState>,
-    ValidJson(json): ValidJson<InsertParams>,
-) -> AppResult<String> {
-    tracing::debug!("开始处理: 添加 Score");
-    throw_err!(json.into_active_model().insert(state.db()).await);
-    tracing::debug!("创建一条 Score 记录.");
-    AppResult::Ok("成功添加 Score 记录!".to_string())
+impl Importable for InsertParams {
+    /// 和 [bulk] 一样, 关闭的学期不能再录入成绩, 并且会留下一条成绩历史
+    ///
+    /// 没有指定录入日期时使用今天, 并且显式地写入, 保证检查过的日期就是保存下来的日期
+    async fn import(self, txn: &DatabaseTransaction, usr: &UserIdent) -> Result<(), AppError> {
+        let date = self.record_date.unwrap_or_else(today);
+        if score_history::term_closed(txn, Some(date)).await? {
+            return Err(AppError::Forbidden(t!("score.term_closed_insert")));
+        }
+
+        let mut active = self.into_active_model();
+        active.record_date = ActiveValue::Set(Some(date));
+        let created = active.insert(txn).await?;
+        score_history::record_direct(txn, None, &created, usr).await?;
+        events::publish(txn, Topic::Score, Action::Insert, usr, &created).await?;
+        Ok(())
+    }
+}
+
+/// 批量录入时, 出现错误的处理方式
+#[derive(Deserialize, Serialize, JsonSchema, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum BulkMode {
+    /// 只要有一行出错, 整批成绩都不会被录入
+    #[default]
+    AllOrNothing,
+    /// 跳过出错的行, 录入其余所有的行
+    BestEffort,
+}
+
+/// 批量录入中的一行, 每一行会被单独校验, 所以这里的校验不会导致整个请求失败
+#[derive(Deserialize, Serialize, Validate, JsonSchema)]
+struct BulkRow {
+    #[validate(length(min = 1, max = 6))]
+    stu_id: String,
+
+    #[validate(range(min = 0, max = 100, code = "score"))]
+    score: Option<i32>,
+
+    record_date: Option<Date>,
 }
 
+/// 为一门课程批量录入成绩所需的参数
+#[derive(Deserialize, Validate, JsonSchema)]
+struct BulkParams {
+    #[validate(length(min = 1, max = 6))]
+    course_id: String,
+
+    #[serde(default)]
+    mode: BulkMode,
+
+    #[validate(length(min = 1, max = 2000, code = "bulk_size"))]
+    items: Vec<BulkRow>,
+}
+
+/// 批量录入中每一行的处理结果
+#[derive(Serialize, JsonSchema)]
+struct BulkRowResult {
+    /// 这一行在请求中的下标, 从 0 开始
+    index: usize,
+    stu_id: String,
+    /// `inserted` 或者 `updated`, 出错时为空
+    action: Option<&'static str>,
+    error: Option<String>,
+}
+
+/// 批量录入的结果报告
+#[derive(Serialize, JsonSchema)]
+struct BulkReport {
+    mode: BulkMode,
+    /// 事务是否被提交, 在 all_or_nothing 模式下有任意一行出错时为 false
+    committed: bool,
+    succeeded: usize,
+    failed: usize,
+    rows: Vec<BulkRowResult>,
+}
+
+/// 为一门课程批量录入成绩, 已经存在的成绩会被覆盖
+///
+/// 所有的行都在同一个事务中处理, 每一行使用一个保存点, 出错的行只会回滚到自己的保存点,
+/// 因此无论哪种模式, 都能拿到每一行的处理结果
 #[debug_handler]
-async fn update(
+async fn bulk(
     State(state): State<ServerState>,
-    Path((stu_id, course_id)): Path<(String, String)>,
-    ValidJson(json): ValidJson<InsertParams>,
-) -> AppResult<Model> {
-    tracing::debug!("开始处理: 更新 Score");
-    let target = throw_err!(Score::find_by_id((stu_id, course_id)).one(state.db()).await);
-    if let Some(score) = target {
-        throw_err!(json.into_active_model().update(state.db()).await);
-        tracing::debug!("成功更新了一条 Score 记录.");
-        AppResult::Ok(score)
+    Extension(usr): Extension<UserIdent>,
+    ValidJson(params): ValidJson<BulkParams>,
+) -> AppResult<BulkReport> {
+    tracing::debug!("开始处理: 批量录入 Score");
+    let txn = throw_err!(state.db().begin().await);
+    let mut seen = HashSet::new();
+    let mut rows = Vec::with_capacity(params.items.len());
+
+    for (index, row) in params.items.into_iter().enumerate() {
+        let result = if let Err(e) = row.validate() {
+            Err(AppError::Validation(e))
+        } else if !seen.insert(row.stu_id.clone()) {
+            Err(AppError::UnprocessableEntity(t!("score.duplicate_student")))
+        } else {
+            enter_row(&txn, &params.course_id, &row, &usr).await
+        };
+
+        let (action, error) = match result {
+            Ok(action) => (Some(action), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        rows.push(BulkRowResult {
+            index,
+            stu_id: row.stu_id,
+            action,
+            error,
+        });
+    }
+
+    let failed = rows.iter().filter(|row| row.error.is_some()).count();
+    let committed = failed == 0 || params.mode == BulkMode::BestEffort;
+    if committed {
+        throw_err!(txn.commit().await);
     } else {
-        AppResult::Err(AppError::NotFound("没有相关的 Score 记录.".to_string()))
+        throw_err!(txn.rollback().await);
     }
+    tracing::info!(
+        "批量录入课程 {} 的成绩: {} 行成功, {failed} 行失败, 是否提交: {committed}",
+        params.course_id,
+        rows.len() - failed
+    );
+
+    AppResult::Ok(BulkReport {
+        mode: params.mode,
+        committed,
+        succeeded: rows.len() - failed,
+        failed,
+        rows,
+    })
 }
 
-#[debug_handler]
-async fn delete(
-    State(state): State<ServerState>,
-    Path((stu_id, course_id)): Path<(String, String)>,
-) -> AppResult<String> {
-    tracing::debug!("开始处理: 删除 Score");
-    let target = throw_err!(
-        Score::find_by_id((stu_id.clone(), course_id.clone()))
-            .one(state.db())
-            .await
-    );
-    if let Some(score) = target {
-        throw_err!(score.delete(state.db()).await);
-        AppResult::Ok(format!(
-            "成功删除一条 Score 记录, student_id 为 {stu_id}, course_id 为 {course_id}."
-        ))
+/// 在一个保存点中录入一行成绩, 出错时只回滚这一行
+async fn enter_row(
+    txn: &DatabaseTransaction,
+    course_id: &str,
+    row: &BulkRow,
+    usr: &UserIdent,
+) -> Result<&'static str, AppError> {
+    let savepoint = txn.begin().await?;
+    match upsert_row(&savepoint, course_id, row, usr).await {
+        Ok(action) => {
+            savepoint.commit().await?;
+            Ok(action)
+        }
+        Err(e) => {
+            savepoint.rollback().await?;
+            Err(e)
+        }
+    }
+}
+
+async fn upsert_row(
+    txn: &DatabaseTransaction,
+    course_id: &str,
+    row: &BulkRow,
+    usr: &UserIdent,
+) -> Result<&'static str, AppError> {
+    let key = (row.stu_id.clone(), course_id.to_string());
+    let closed_error = || AppError::Forbidden(t!("score.term_closed"));
+
+    if let Some(old) = Score::find_by_id(key).one(txn).await? {
+        // 和 before_update 一样, 修改前后的日期所在的学期都不能是关闭的
+        let new_date = row.record_date.or(old.record_date);
+        for date in [old.record_date, new_date] {
+            if score_history::term_closed(txn, date).await? {
+                return Err(closed_error());
+            }
+        }
+        let mut active: ActiveModel = old.clone().into();
+        active.score = ActiveValue::Set(row.score);
+        active.record_date = ActiveValue::Set(new_date);
+        let updated = active.update(txn).await?;
+        score_history::record_direct(txn, Some(&old), &updated, usr).await?;
+        events::publish(txn, Topic::Score, Action::Update, usr, &updated).await?;
+        Ok("updated")
     } else {
-        AppResult::Err(AppError::NotFound("没有相关的 Score 记录.".to_string()))
+        let date = row.record_date.unwrap_or_else(today);
+        if score_history::term_closed(txn, Some(date)).await? {
+            return Err(closed_error());
+        }
+        let created = ActiveModel {
+            stu_id: ActiveValue::Set(row.stu_id.clone()),
+            course_id: ActiveValue::Set(course_id.to_string()),
+            score: ActiveValue::Set(row.score),
+            record_date: ActiveValue::Set(Some(date)),
+        }
+        .insert(txn)
+        .await?;
+        score_history::record_direct(txn, None, &created, usr).await?;
+        events::publish(txn, Topic::Score, Action::Insert, usr, &created).await?;
+        Ok("inserted")
     }
 }
 
-#[derive(Validate, Deserialize)]
-struct QueryParams {
+/// 没有指定录入日期时使用的日期, 和数据库中 `record_date` 的默认值相同
+fn today() -> Date {
+    chrono::Local::now().date_naive()
+}
+
+#[derive(Validate, Deserialize, JsonSchema)]
+pub struct QueryParams {
     student: Option<String>,
 
     course: Option<String>,
+}
+
+/// `/detailed` 可以筛选和排序的列
+const DETAILED_COLUMNS: &[detailed::Column] = &[
+    detailed::Column::StuId,
+    detailed::Column::StuName,
+    detailed::Column::CourseId,
+    detailed::Column::CourseName,
+    detailed::Column::Score,
+    detailed::Column::RecordDate,
+];
+
+/// 路由到 score 模块下的 detailed 板块时的所需的参数
+#[derive(Validate, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct DetailedParams {
+    /// 按学生姓名模糊查询
+    #[validate(length(max = 20))]
+    student: Option<String>,
+
+    /// 按课程名称模糊查询
+    #[validate(length(max = 20))]
+    course: Option<String>,
+
+    #[validate(length(max = 6))]
+    stu_id: Option<String>,
+
+    #[validate(length(max = 6))]
+    course_id: Option<String>,
+
+    /// 排序的字段, 形如 `sort=-score,record_date`, 最后总会按学号和课程号排序
+    #[serde(default)]
+    sort: SortParam,
+
+    /// 指定时导出全部结果, 而不是返回一页
+    format: Option<ExportFormat>,
+
+    /// 结果中只需要的字段, 导出时会被忽略
+    #[serde(default)]
+    fields: FieldsParam,
 
     #[validate(nested)]
     #[serde(flatten)]
     page: PageParam,
 }
 
-/// 处理路由到 score 模块下的查询请求
+/// 通过 student_score_course 视图查询成绩, 结果中直接带有学生姓名和课程名称
 #[debug_handler]
-async fn query(
+async fn detailed(
     State(state): State<ServerState>,
-    ValidQuery(params): ValidQuery<QueryParams>,
-) -> AppResult<Page<Model>> {
-    tracing::debug!("开始处理: 查询 score");
-    let pagination = Score::find()
+    headers: HeaderMap,
+    ValidQuery(params): ValidQuery<DetailedParams>,
+    filters: Filters,
+) -> Response {
+    tracing::debug!("开始处理: 查询 score 详情");
+    let condition = match filters.condition(DETAILED_COLUMNS) {
+        Ok(condition) => condition,
+        Err(e) => return e.into_response(),
+    };
+    let select = StudentScoreCourse::find()
+        .filter(condition)
         .apply_if(params.student, |rows, keyword| {
-            rows.join(
-                JoinType::InnerJoin,
-                student::Relation::Score
-                    .def()
-                    .rev()
-                    .on_condition(move |_score, student_name| {
-                        Expr::col((student_name, student::Column::Name))
-                            .like(format!("%{keyword}%"))
-                            .into_condition()
-                    }),
-            )
+            rows.filter(detailed::Column::StuName.contains(keyword))
         })
         .apply_if(params.course, |rows, keyword| {
-            rows.join(
-                JoinType::InnerJoin,
-                course::Relation::Score
-                    .def()
-                    .rev()
-                    .on_condition(move |_score, course_name| {
-                        Expr::col((course_name, course::Column::Name))
-                            .like(format!("%{keyword}%"))
-                            .into_condition()
-                    }),
-            )
+            rows.filter(detailed::Column::CourseName.contains(keyword))
         })
-        .paginate(state.db(), params.page.size);
+        .apply_if(params.stu_id, |rows, id| {
+            rows.filter(detailed::Column::StuId.eq(id))
+        })
+        .apply_if(params.course_id, |rows, id| {
+            rows.filter(detailed::Column::CourseId.eq(id))
+        });
+    let select = match params.sort.apply(select, DETAILED_COLUMNS) {
+        Ok(select) => select,
+        Err(e) => return e.into_response(),
+    };
 
-    let total = throw_err!(pagination.num_pages().await);
-    let items = throw_err!(pagination.fetch_page(params.page.index - 1).await);
+    if let Some(format) = ExportFormat::negotiate(params.format, &headers) {
+        return export::export(select, state.db(), format, "score-detailed");
+    }
+    if !params.fields.is_empty() {
+        let page = fields::fetch_page(select, state.db(), params.page, &params.fields);
+        return AppResult::from(page.await).into_response();
+    }
+    AppResult::from(Page::fetch(select, state.db(), params.page).await).into_response()
+}
 
-    AppResult::Ok(Page {
-        param: params.page,
-        total,
-        items,
-    })
+/// 部分更新成绩所需的参数, 只有请求中出现了的字段会被更新, 显式地传 null 会把字段置空
+#[derive(Deserialize, Validate, JsonSchema)]
+#[schemars(rename = "ScorePatchParams")]
+pub struct PatchParams {
+    #[validate(range(min = 0, max = 100, code = "score"))]
+    #[serde(default, deserialize_with = "patch::nullable")]
+    score: Option<Option<i32>>,
+
+    #[serde(default, deserialize_with = "patch::nullable")]
+    record_date: Option<Option<Date>>,
+}
+
+impl IntoActiveModel<ActiveModel> for PatchParams {
+    fn into_active_model(self) -> ActiveModel {
+        ActiveModel {
+            score: patch::present(self.score),
+            record_date: patch::present(self.record_date),
+            ..Default::default()
+        }
+    }
 }
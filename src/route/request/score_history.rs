@@ -0,0 +1,426 @@
+use crate::entity::prelude::{Score, ScoreHistory, Term};
+use crate::entity::score_history::{ActiveModel, Column, Model};
+use crate::entity::{score, term};
+use crate::error::{self, AppError};
+use crate::route::extract::{Path, ValidJson, ValidPath, ValidQuery};
+use crate::route::fields::{self, FieldsParam};
+use crate::route::filter::Filters;
+use crate::route::include::{self, Embedded, IncludeParam};
+use crate::route::openapi::{ApiDoc, Operation};
+use crate::route::page::{Page, PageParam};
+use crate::route::request::events::{self, Action, Topic};
+use crate::route::request::login::{Role, UserIdent};
+use crate::route::result::AppResult;
+use crate::route::routes::Routes;
+use crate::route::sort::SortParam;
+use crate::server::ServerState;
+use crate::t;
+use crate::throw_err;
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, debug_handler};
+use schemars::JsonSchema;
+use sea_orm::prelude::Date;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbErr, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait, TransactionTrait,
+};
+use serde::Deserialize;
+use validator::Validate;
+
+/// 学期未关闭时直接录入或修改成绩留下的记录, 不需要审批
+pub const STATUS_DIRECT: &str = "direct";
+/// 等待教务处审批的成绩修改申请
+pub const STATUS_PENDING: &str = "pending";
+/// 已通过审批, 成绩已被修改
+pub const STATUS_APPROVED: &str = "approved";
+/// 已被驳回, 成绩保持不变
+pub const STATUS_REJECTED: &str = "rejected";
+/// 学期未关闭时成绩被删除, `old_score` 为删除前的成绩
+pub const STATUS_DELETED: &str = "deleted";
+
+pub fn router() -> Routes {
+    Routes::new()
+        .get("/", index)
+        .get("/query", query)
+        .post("/propose", propose)
+        .put("/approve/{id}", approve)
+        .put("/reject/{id}", reject)
+        .get("/{stu_id}/{course_id}", history)
+}
+
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    doc.get(
+        "/",
+        Operation::new("Score History 的首页").returns::<String>(),
+    )
+    .get(
+        "/query",
+        Operation::new("查询成绩修改申请")
+            .query::<QueryParams>()
+            .filter(COLUMNS)
+            .returns::<Page<Embedded<Model>>>(),
+    )
+    .post(
+        "/propose",
+        Operation::new("提交成绩修改申请")
+            .description("需要教师角色, 成绩在审批通过之前不会改变.")
+            .json::<ProposeParams>()
+            .returns::<Model>()
+            .error("409", "这条成绩已经有一条待审批的修改申请了"),
+    )
+    .put(
+        "/approve/{id}",
+        Operation::new("通过成绩修改申请")
+            .description("需要教务角色.")
+            .path::<i32>("id")
+            .json::<ReviewParams>()
+            .returns::<Model>(),
+    )
+    .put(
+        "/reject/{id}",
+        Operation::new("驳回成绩修改申请")
+            .description("需要教务角色.")
+            .path::<i32>("id")
+            .json::<ReviewParams>()
+            .returns::<Model>(),
+    )
+    .get(
+        "/{stu_id}/{course_id}",
+        Operation::new("列出一条成绩的所有版本").returns::<Vec<Model>>(),
+    );
+}
+
+/// 路由到 score-history 模块下的默认界面
+#[debug_handler]
+async fn index() -> AppResult<String> {
+    AppResult::Ok(t!("crud.welcome", entity = "Score History"))
+}
+
+/// 判断 `date` 所在的学期是否已经关闭, 不属于任何学期的成绩视为未关闭
+pub async fn term_closed<C: ConnectionTrait>(db: &C, date: Option<Date>) -> Result<bool, DbErr> {
+    let Some(date) = date else {
+        return Ok(false);
+    };
+
+    let closed = Term::find()
+        .filter(term::Column::StartDate.lte(date))
+        .filter(term::Column::EndDate.gte(date))
+        .filter(term::Column::Closed.eq(true))
+        .count(db)
+        .await?;
+    Ok(closed > 0)
+}
+
+/// 记录一次不经过审批的成绩变更, 学期未关闭时的录入和修改都会经过这里
+pub async fn record_direct<C: ConnectionTrait>(
+    db: &C,
+    old: Option<&score::Model>,
+    new: &score::Model,
+    usr: &UserIdent,
+) -> Result<Model, DbErr> {
+    let now = chrono::Local::now().naive_local();
+    ActiveModel {
+        stu_id: ActiveValue::Set(new.stu_id.clone()),
+        course_id: ActiveValue::Set(new.course_id.clone()),
+        old_score: ActiveValue::Set(old.and_then(|score| score.score)),
+        new_score: ActiveValue::Set(new.score),
+        status: ActiveValue::Set(STATUS_DIRECT.to_string()),
+        proposed_by: ActiveValue::Set(usr.id.clone()),
+        proposed_at: ActiveValue::Set(now),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// 记录一次成绩的删除, 删除之后历史仍然保留
+pub async fn record_delete<C: ConnectionTrait>(
+    db: &C,
+    old: &score::Model,
+    usr: &UserIdent,
+) -> Result<Model, DbErr> {
+    ActiveModel {
+        stu_id: ActiveValue::Set(old.stu_id.clone()),
+        course_id: ActiveValue::Set(old.course_id.clone()),
+        old_score: ActiveValue::Set(old.score),
+        new_score: ActiveValue::Set(None),
+        status: ActiveValue::Set(STATUS_DELETED.to_string()),
+        proposed_by: ActiveValue::Set(usr.id.clone()),
+        proposed_at: ActiveValue::Set(chrono::Local::now().naive_local()),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+/// 成绩修改申请所需要的参数
+#[derive(Deserialize, Validate, JsonSchema)]
+struct ProposeParams {
+    #[validate(length(min = 1, max = 6))]
+    stu_id: String,
+
+    #[validate(length(min = 1, max = 6))]
+    course_id: String,
+
+    #[validate(range(min = 0, max = 100, code = "score"))]
+    score: Option<i32>,
+
+    #[validate(length(min = 1, max = 200, code = "reason"))]
+    reason: String,
+}
+
+/// `(stu_id, course_id)` 上只包含待审批申请的唯一索引
+const PENDING_INDEX: &str = "score_history_pending_idx";
+
+/// 教师提交一条成绩修改申请, 成绩在审批通过之前不会改变
+#[debug_handler]
+async fn propose(
+    State(state): State<ServerState>,
+    Extension(usr): Extension<UserIdent>,
+    ValidJson(params): ValidJson<ProposeParams>,
+) -> AppResult<Model> {
+    tracing::debug!("开始处理: 提交成绩修改申请");
+    throw_err!(usr.require(&[Role::Teacher]));
+
+    let key = (params.stu_id.clone(), params.course_id.clone());
+    let target = throw_err!(Score::find_by_id(key).one(state.db()).await);
+    let Some(current) = target else {
+        return AppResult::Err(AppError::NotFound(t!("crud.not_found", entity = "Score")));
+    };
+
+    let request = ActiveModel {
+        stu_id: ActiveValue::Set(params.stu_id),
+        course_id: ActiveValue::Set(params.course_id),
+        old_score: ActiveValue::Set(current.score),
+        new_score: ActiveValue::Set(params.score),
+        reason: ActiveValue::Set(Some(params.reason)),
+        status: ActiveValue::Set(STATUS_PENDING.to_string()),
+        proposed_by: ActiveValue::Set(usr.id.clone()),
+        proposed_at: ActiveValue::Set(chrono::Local::now().naive_local()),
+        ..Default::default()
+    };
+    let txn = throw_err!(state.db().begin().await);
+    // 每条成绩最多只有一条待审批的申请, 由唯一索引保证, 并发的申请中只有一个能插入成功
+    let request = match request.insert(&txn).await {
+        Err(e) if error::violated_constraint(&e) == Some(PENDING_INDEX) => {
+            return AppResult::Err(AppError::Conflict(t!("score_history.already_pending")));
+        }
+        request => throw_err!(request),
+    };
+    throw_err!(events::publish(&txn, Topic::ScoreHistory, Action::Insert, &usr, &request).await);
+    throw_err!(txn.commit().await);
+    tracing::info!("{} 提交了一条成绩修改申请, id 为 {}", usr.id, request.id);
+    AppResult::Ok(request)
+}
+
+/// 审批成绩修改申请时可以附带的参数
+#[derive(Deserialize, Validate, JsonSchema)]
+struct ReviewParams {
+    #[validate(length(max = 200))]
+    comment: Option<String>,
+}
+
+/// 教务处通过一条成绩修改申请, 此时才会真正修改成绩
+#[debug_handler]
+async fn approve(
+    State(state): State<ServerState>,
+    Extension(usr): Extension<UserIdent>,
+    Path(id): Path<i32>,
+    ValidJson(params): ValidJson<ReviewParams>,
+) -> AppResult<Model> {
+    tracing::debug!("开始处理: 通过成绩修改申请");
+    review(state, usr, id, params, true).await
+}
+
+/// 教务处驳回一条成绩修改申请, 成绩保持不变
+#[debug_handler]
+async fn reject(
+    State(state): State<ServerState>,
+    Extension(usr): Extension<UserIdent>,
+    Path(id): Path<i32>,
+    ValidJson(params): ValidJson<ReviewParams>,
+) -> AppResult<Model> {
+    tracing::debug!("开始处理: 驳回成绩修改申请");
+    review(state, usr, id, params, false).await
+}
+
+/// 在同一个事务中完成审批和成绩的修改, 申请记录会被加锁, 以免两个人同时审批同一条申请
+async fn review(
+    state: ServerState,
+    usr: UserIdent,
+    id: i32,
+    params: ReviewParams,
+    approved: bool,
+) -> AppResult<Model> {
+    throw_err!(usr.require(&[Role::Registrar]));
+
+    let txn = throw_err!(state.db().begin().await);
+    let target = throw_err!(
+        ScoreHistory::find_by_id(id)
+            .lock_exclusive()
+            .one(&txn)
+            .await
+    );
+    let Some(request) = target else {
+        return AppResult::Err(AppError::NotFound(t!("score_history.not_found")));
+    };
+    if request.status != STATUS_PENDING {
+        return AppResult::Err(AppError::UnprocessableEntity(t!(
+            "score_history.already_reviewed",
+            status = request.status,
+        )));
+    }
+
+    let key = (request.stu_id.clone(), request.course_id.clone());
+    let new_score = request.new_score;
+    let mut request: ActiveModel = request.into();
+    if approved {
+        let target = throw_err!(Score::find_by_id(key).lock_exclusive().one(&txn).await);
+        let Some(current) = target else {
+            return AppResult::Err(AppError::NotFound(t!("score_history.score_gone")));
+        };
+
+        // 申请提交之后成绩可能已经被改过了, 以实际被覆盖的值为准
+        request.old_score = ActiveValue::Set(current.score);
+        let mut current: score::ActiveModel = current.into();
+        current.score = ActiveValue::Set(new_score);
+        let updated = throw_err!(current.update(&txn).await);
+        throw_err!(events::publish(&txn, Topic::Score, Action::Update, &usr, &updated).await);
+    }
+
+    request.status = ActiveValue::Set(
+        if approved {
+            STATUS_APPROVED
+        } else {
+            STATUS_REJECTED
+        }
+        .to_string(),
+    );
+    request.reviewed_by = ActiveValue::Set(Some(usr.id.clone()));
+    request.reviewed_at = ActiveValue::Set(Some(chrono::Local::now().naive_local()));
+    request.review_comment = ActiveValue::Set(params.comment);
+    let request = throw_err!(request.update(&txn).await);
+    throw_err!(events::publish(&txn, Topic::ScoreHistory, Action::Update, &usr, &request).await);
+    throw_err!(txn.commit().await);
+
+    tracing::info!(
+        "{} 处理了 id 为 {id} 的成绩修改申请, 结果: {}",
+        usr.id,
+        request.status
+    );
+    AppResult::Ok(request)
+}
+
+/// 定位一条成绩的路径参数
+#[derive(Deserialize, Validate, JsonSchema)]
+struct ScoreKey {
+    #[validate(length(min = 1, max = 6))]
+    stu_id: String,
+
+    #[validate(length(min = 1, max = 6))]
+    course_id: String,
+}
+
+/// 按时间顺序列出某个学生某门课程成绩的所有版本
+#[debug_handler]
+async fn history(
+    State(state): State<ServerState>,
+    ValidPath(key): ValidPath<ScoreKey>,
+) -> AppResult<Vec<Model>> {
+    tracing::debug!("开始处理: 查询成绩历史");
+    let versions = throw_err!(
+        ScoreHistory::find()
+            .filter(Column::StuId.eq(key.stu_id))
+            .filter(Column::CourseId.eq(key.course_id))
+            .order_by_asc(Column::ProposedAt)
+            .order_by_asc(Column::Id)
+            .all(state.db())
+            .await
+    );
+    AppResult::Ok(versions)
+}
+
+/// query 板块可以筛选和排序的列, 审批意见和理由不在其中
+const COLUMNS: &[Column] = &[
+    Column::Id,
+    Column::StuId,
+    Column::CourseId,
+    Column::OldScore,
+    Column::NewScore,
+    Column::Status,
+    Column::ProposedBy,
+    Column::ProposedAt,
+    Column::ReviewedBy,
+    Column::ReviewedAt,
+];
+
+/// 路由到 score-history 模块下的 query 板块时的所需的参数
+#[derive(Deserialize, Validate, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct QueryParams {
+    #[validate(length(max = 6))]
+    stu_id: Option<String>,
+
+    #[validate(length(max = 6))]
+    course_id: Option<String>,
+
+    #[validate(length(max = 16))]
+    status: Option<String>,
+
+    /// 要嵌入到结果中的关联数据
+    #[serde(default)]
+    include: IncludeParam,
+
+    /// 结果中只需要的字段, 不能和 include 一起使用
+    #[serde(default)]
+    fields: FieldsParam,
+
+    /// 排序的字段, 形如 `sort=-proposed_at`, 最后总会按 id 排序
+    #[serde(default)]
+    sort: SortParam,
+
+    #[validate(nested)]
+    #[serde(flatten)]
+    page: PageParam,
+}
+
+/// 处理路由到 score-history 模块下的查询请求, 可以按学生, 课程和状态筛选
+#[debug_handler]
+async fn query(
+    State(state): State<ServerState>,
+    ValidQuery(params): ValidQuery<QueryParams>,
+    filters: Filters,
+) -> Response {
+    tracing::debug!("开始处理: 查询 Score History");
+    let condition = match filters.condition(COLUMNS) {
+        Ok(condition) => condition,
+        Err(e) => return e.into_response(),
+    };
+    let select = ScoreHistory::find()
+        .filter(condition)
+        .apply_if(params.stu_id.as_ref(), |rows, id| {
+            rows.filter(Column::StuId.eq(id))
+        })
+        .apply_if(params.course_id.as_ref(), |rows, id| {
+            rows.filter(Column::CourseId.eq(id))
+        })
+        .apply_if(params.status.as_ref(), |rows, status| {
+            rows.filter(Column::Status.eq(status))
+        });
+    let select = match params.sort.apply(select, COLUMNS) {
+        Ok(select) => select,
+        Err(e) => return e.into_response(),
+    };
+    if let Err(e) = params.fields.check(&params.include) {
+        return e.into_response();
+    }
+
+    if !params.fields.is_empty() {
+        let page = fields::fetch_page(select, state.db(), params.page, &params.fields);
+        return AppResult::from(page.await).into_response();
+    }
+    let page = include::fetch_page(select, state.db(), params.page, &params.include);
+    AppResult::from(page.await).into_response()
+}
@@ -0,0 +1,228 @@
+use crate::comma_list;
+use crate::route::extract::ValidQuery;
+use crate::route::openapi::{ApiDoc, Operation};
+use crate::route::result::AppResult;
+use crate::route::routes::Routes;
+use crate::server::ServerState;
+use crate::throw_err;
+use axum::debug_handler;
+use axum::extract::State;
+use schemars::JsonSchema;
+use sea_orm::{DbBackend, FromQueryResult, Statement};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+pub fn router() -> Routes {
+    Routes::new().get("/", search)
+}
+
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    doc.get(
+        "/",
+        Operation::new("按名称搜索学生, 课程和学院")
+            .query::<SearchParams>()
+            .returns::<Vec<Hit>>(),
+    );
+}
+
+/// 可以被搜索的资源, 序列化后作为结果中的 `type`
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    Student,
+    Course,
+    Department,
+}
+
+impl Kind {
+    const ALL: [Kind; 3] = [Kind::Student, Kind::Course, Kind::Department];
+
+    fn table(self) -> &'static str {
+        match self {
+            Kind::Student => "student",
+            Kind::Course => "course",
+            Kind::Department => "department",
+        }
+    }
+}
+
+/// `types` 参数, 形如 `types=student,course`, 不填时搜索全部资源
+#[derive(Default, Debug, PartialEq)]
+struct Kinds(Vec<Kind>);
+
+comma_list!(Kinds, "student,course");
+
+/// 路由到 search 模块时所需的参数
+#[derive(Deserialize, Validate, JsonSchema)]
+struct SearchParams {
+    /// 搜索的关键字
+    #[validate(length(min = 1, max = 50, code = "search_keyword"))]
+    q: String,
+
+    #[serde(default)]
+    types: Kinds,
+
+    /// 最多返回多少条结果
+    #[validate(range(min = 1, max = 50, code = "search_limit"))]
+    #[serde(default = "SearchParams::default_limit")]
+    limit: u64,
+}
+
+impl SearchParams {
+    fn default_limit() -> u64 {
+        20
+    }
+}
+
+/// 一条搜索结果, `highlight` 中与关键字匹配的部分被 `<em>` 包裹, 其余部分已做 HTML 转义
+#[derive(Serialize, JsonSchema, Debug)]
+struct Hit {
+    #[serde(rename = "type")]
+    kind: Kind,
+    id: String,
+    name: String,
+    score: f32,
+    highlight: String,
+}
+
+#[derive(FromQueryResult)]
+struct Row {
+    kind: String,
+    id: String,
+    name: String,
+    score: f32,
+}
+
+/// 按名称搜索学生, 课程和学院, 结果按相关度从高到低排列
+///
+/// 相关度取 pg_trgm 的 `word_similarity` 和子串匹配两者中的较大值: 完全相同为 1, 前缀为 0.9, 包含为 0.7.
+/// 子串匹配保证了在 pg_trgm 无法从中文中提取三元组的数据库 (`LC_CTYPE` 为 C) 上也能搜到结果
+#[debug_handler]
+async fn search(
+    State(state): State<ServerState>,
+    ValidQuery(params): ValidQuery<SearchParams>,
+) -> AppResult<Vec<Hit>> {
+    tracing::debug!("开始处理: 搜索 {}", params.q);
+    let kinds = if params.types.0.is_empty() {
+        Kind::ALL.to_vec()
+    } else {
+        params.types.0
+    };
+
+    let sql = kinds
+        .iter()
+        .map(|kind| {
+            let table = kind.table();
+            format!(
+                "SELECT '{table}' AS kind, id, name, GREATEST(word_similarity($1, name), \
+                 CASE WHEN lower(name) = lower($1) THEN 1 WHEN name ILIKE $3 THEN 0.9 \
+                 WHEN name ILIKE $2 THEN 0.7 ELSE 0 END)::real AS score \
+                 FROM {table} WHERE $1 <% name OR name ILIKE $2"
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ")
+        + " ORDER BY score DESC, kind, id LIMIT $4";
+
+    let escaped = escape_like(&params.q);
+    let statement = Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        sql,
+        [
+            params.q.clone().into(),
+            format!("%{escaped}%").into(),
+            format!("{escaped}%").into(),
+            (params.limit as i64).into(),
+        ],
+    );
+    let rows = throw_err!(Row::find_by_statement(statement).all(state.db()).await);
+
+    let hits = rows
+        .into_iter()
+        .filter_map(|row| {
+            let kind = Kind::ALL
+                .into_iter()
+                .find(|kind| kind.table() == row.kind)?;
+            let id = row.id.trim_end().to_string();
+            Some(Hit {
+                kind,
+                highlight: highlight(&row.name, &params.q),
+                id,
+                name: row.name,
+                score: row.score,
+            })
+        })
+        .collect();
+    AppResult::Ok(hits)
+}
+
+/// 转义 LIKE 模式中的通配符
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// 用 `<em>` 标出 `name` 中和 `q` 匹配的部分
+///
+/// `name` 包含 `q` 时只标出第一处, 否则 (模糊匹配) 标出所有在 `q` 中出现过的字符, 比较时忽略大小写
+fn highlight(name: &str, q: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let lower = |c: &char| c.to_lowercase().collect::<String>();
+    let name_lower: Vec<String> = chars.iter().map(lower).collect();
+    let q_lower: Vec<String> = q.trim().chars().map(|c| lower(&c)).collect();
+
+    let mut marked = vec![false; chars.len()];
+    let found = (!q_lower.is_empty())
+        .then(|| name_lower.windows(q_lower.len()).position(|w| w == q_lower))
+        .flatten();
+    match found {
+        Some(start) => marked[start..start + q_lower.len()].fill(true),
+        None => {
+            for (mark, c) in marked.iter_mut().zip(&name_lower) {
+                *mark = !c.trim().is_empty() && q_lower.contains(c);
+            }
+        }
+    }
+
+    let mut result = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if marked[i] && (i == 0 || !marked[i - 1]) {
+            result.push_str("<em>");
+        }
+        match c {
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '&' => result.push_str("&amp;"),
+            '"' => result.push_str("&quot;"),
+            c => result.push(*c),
+        }
+        if marked[i] && (i + 1 == chars.len() || !marked[i + 1]) {
+            result.push_str("</em>");
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(highlight("李佳伟", "佳伟"), "李<em>佳伟</em>");
+        assert_eq!(highlight("吴莫愁", "吴莫仇"), "<em>吴莫</em>愁");
+        assert_eq!(
+            highlight("Database Systems", "data"),
+            "<em>Data</em>base Systems"
+        );
+        assert_eq!(highlight("C<C++>", "c++"), "C&lt;<em>C++</em>&gt;");
+        assert_eq!(escape_like("100%_\\"), "100\\%\\_\\\\");
+    }
+}
@@ -1,42 +1,103 @@
+// DeriveIntoActiveModel 展开后总会带上 `..Default::default()`, InsertParams 的字段齐全时会触发这个 lint
+#![allow(clippy::needless_update)]
+
 use crate::entity::prelude::Student;
 use crate::entity::student::ActiveModel;
-use crate::entity::student::Model;
 use crate::entity::{department, student};
 use crate::error::AppError;
-use crate::route::extract::{Path, ValidJson, ValidQuery};
-use crate::route::page::{Page, PageParam};
-use crate::route::result::AppResult;
-use crate::server::ServerState;
-use crate::throw_err;
-use axum::extract::State;
-use axum::{Router, debug_handler, routing};
+use crate::route::crud::{self, CrudResource};
+use crate::route::openapi::{ApiDoc, Operation};
+use crate::route::patch;
+use crate::route::request::events::{self, Action, Topic};
+use crate::route::request::import::Importable;
+use crate::route::request::login::UserIdent;
+use crate::route::request::transcript;
+use crate::route::routes::Routes;
+use crate::route::version::ApiVersion;
+use async_graphql::InputObject;
+use schemars::JsonSchema;
 use sea_orm::prelude::Expr;
 use sea_orm::sea_query::IntoCondition;
 use sea_orm::{
-    ActiveModelTrait, ColumnTrait, DeriveIntoActiveModel, EntityTrait, IntoActiveModel, JoinType,
-    ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait, RelationTrait,
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, DeriveIntoActiveModel, IntoActiveModel,
+    JoinType, QueryFilter, QuerySelect, QueryTrait, RelationTrait, Select,
 };
 use serde::Deserialize;
 use validator::Validate;
 
-pub fn router() -> Router<ServerState> {
-    Router::new()
-        .route("/", routing::get(index))
-        .route("/query", routing::get(query))
-        .route("/insert", routing::post(insert))
-        .route("/update/{id}", routing::put(update))
-        .route("/delete/{id}", routing::delete(delete))
+pub fn router(version: ApiVersion) -> Routes {
+    crud::router::<Student>(version).get("/{id}/transcript.pdf", transcript::transcript)
 }
 
-/// 路由到 student 模块下的默认界面
-#[debug_handler]
-async fn index() -> AppResult<&'static str> {
-    AppResult::Ok("欢迎! 这是 Student 的首页.")
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    crud::document::<Student>(doc);
+    doc.get(
+        "/{id}/transcript.pdf",
+        Operation::new("生成 PDF 成绩单")
+            .description("每一份成绩单都有一个验证码, 可以通过 `/api/verify/{code}` 核验.")
+            .returns_content("application/pdf"),
+    );
+}
+
+impl CrudResource for Student {
+    const NAME: &'static str = "Student";
+    const TOPIC: Topic = Topic::Student;
+    const FILTERABLE: &'static [student::Column] = &[
+        student::Column::Id,
+        student::Column::Name,
+        student::Column::Sex,
+        student::Column::Age,
+        student::Column::Email,
+        student::Column::DepartmentId,
+    ];
+    const SORTABLE: &'static [student::Column] = &[
+        student::Column::Id,
+        student::Column::Name,
+        student::Column::Age,
+        student::Column::DepartmentId,
+    ];
+
+    type Key = String;
+    type Insert = InsertParams;
+    type Update = InsertParams;
+    type Patch = PatchParams;
+    type Filter = QueryParams;
+
+    fn filter(select: Select<Self>, params: QueryParams) -> Select<Self> {
+        select
+            .apply_if(params.department, |rows, keyword| {
+                rows.join(
+                    JoinType::InnerJoin,
+                    department::Relation::Student.def().rev().on_condition(
+                        move |_student, department_name| {
+                            Expr::col((department_name, department::Column::Name))
+                                .like(format!("%{keyword}%"))
+                                .into_condition()
+                        },
+                    ),
+                )
+            })
+            .apply_if(params.keyword.as_ref(), |rows, keyword| {
+                rows.filter(student::Column::Name.contains(keyword))
+            })
+            .apply_if(params.email.as_ref(), |rows, keyword| {
+                rows.filter(student::Column::Email.contains(keyword))
+            })
+            .apply_if(params.sex.as_ref(), |rows, keyword| {
+                rows.filter(student::Column::Sex.eq(keyword))
+            })
+            .apply_if(params.age, |rows, keyword| {
+                rows.filter(student::Column::Age.eq(keyword))
+            })
+    }
 }
 
 /// 路由到 student 模块下的 insert 模块时所需的参数
-#[derive(Deserialize, Validate, DeriveIntoActiveModel)]
-struct InsertParams {
+#[derive(Deserialize, Validate, DeriveIntoActiveModel, JsonSchema, InputObject)]
+#[schemars(rename = "StudentInsertParams")]
+#[graphql(name = "StudentInput")]
+pub struct InsertParams {
     #[validate(length(min = 1, max = 6))]
     id: String,
 
@@ -56,50 +117,55 @@ struct InsertParams {
     department_id: Option<String>,
 }
 
-#[debug_handler]
-async fn insert(
-    State(state): State<ServerState>,
-    ValidJson(params): ValidJson<InsertParams>,
-) -> AppResult<String> {
-    tracing::debug!("开始处理: 添加 Student");
-    let new_student = params.into_active_model();
-    let _result = throw_err!(Student::insert(new_student).exec(state.db()).await);
-    AppResult::Ok("成功添加一条 Student 记录!".to_string())
+impl Importable for InsertParams {
+    async fn import(self, txn: &DatabaseTransaction, usr: &UserIdent) -> Result<(), AppError> {
+        let created = self.into_active_model().insert(txn).await?;
+        events::publish(txn, Topic::Student, Action::Insert, usr, &created).await?;
+        Ok(())
+    }
 }
 
-#[debug_handler]
-async fn update(
-    State(state): State<ServerState>,
-    Path(id): Path<String>,
-    ValidJson(params): ValidJson<InsertParams>,
-) -> AppResult<String> {
-    tracing::debug!("开始处理: 更新 Student");
-    let target = throw_err!(Student::find_by_id(&id).one(state.db()).await);
-    if target.is_some() {
-        throw_err!(params.into_active_model().update(state.db()).await);
-        AppResult::Ok(format!("成功更新一条 id 为 {id} 的 Student 记录!"))
-    } else {
-        AppResult::Err(AppError::NotFound("没有相关的 Student 记录".to_string()))
-    }
+/// 部分更新 student 所需的参数, 只有请求中出现了的字段会被更新, 显式地传 null 会把可以为空的字段置空
+#[derive(Deserialize, Validate, JsonSchema)]
+#[schemars(rename = "StudentPatchParams")]
+pub struct PatchParams {
+    #[validate(length(min = 1, max = 20))]
+    name: Option<String>,
+
+    #[validate(length(min = 1, max = 2))]
+    #[serde(default, deserialize_with = "patch::nullable")]
+    sex: Option<Option<String>>,
+
+    #[validate(range(min = 0))]
+    #[serde(default, deserialize_with = "patch::nullable")]
+    age: Option<Option<i32>>,
+
+    #[validate(email)]
+    #[serde(default, deserialize_with = "patch::nullable")]
+    email: Option<Option<String>>,
+
+    #[validate(length(max = 2))]
+    #[serde(default, deserialize_with = "patch::nullable")]
+    department_id: Option<Option<String>>,
 }
 
-#[debug_handler]
-async fn delete(State(state): State<ServerState>, Path(id): Path<String>) -> AppResult<String> {
-    tracing::debug!("开始处理: 删除 student");
-    let target = throw_err!(Student::find_by_id(&id).one(state.db()).await);
-    if let Some(student) = target {
-        throw_err!(student.delete(state.db()).await);
-        tracing::info!("已删除 id 为 {id} 的 Student");
-        AppResult::Ok(format!("成功删除 id 为 {id} 的学生!"))
-    } else {
-        AppResult::Err(AppError::NotFound("没有相关的 Student 记录".to_string()))
+impl IntoActiveModel<ActiveModel> for PatchParams {
+    fn into_active_model(self) -> ActiveModel {
+        ActiveModel {
+            name: patch::present(self.name),
+            sex: patch::present(self.sex),
+            age: patch::present(self.age),
+            email: patch::present(self.email),
+            department_id: patch::present(self.department_id),
+            ..Default::default()
+        }
     }
 }
 
 /// 路由到 student 模块下的 query 板块时的所需的参数
-#[derive(Deserialize, Validate)]
+#[derive(Deserialize, Validate, JsonSchema)]
 #[serde(rename_all = "camelCase")]
-struct QueryParams {
+pub struct QueryParams {
     keyword: Option<String>,
     department: Option<String>,
 
@@ -111,53 +177,4 @@ struct QueryParams {
 
     #[validate(range(min = 0))]
     age: Option<i32>,
-
-    #[validate(nested)]
-    #[serde(flatten)]
-    page: PageParam,
-}
-
-/// 处理路由到 student 模块下的查询请求
-#[debug_handler]
-async fn query(
-    State(state): State<ServerState>,
-    ValidQuery(params): ValidQuery<QueryParams>,
-) -> AppResult<Page<Model>> {
-    tracing::debug!("开始处理: Query student");
-    let pagination = Student::find()
-        .apply_if(params.department, |rows, keyword| {
-            rows.join(
-                JoinType::InnerJoin,
-                department::Relation::Student.def().rev().on_condition(
-                    move |_student, department_name| {
-                        Expr::col((department_name, department::Column::Name))
-                            .like(format!("%{keyword}%"))
-                            .into_condition()
-                    },
-                ),
-            )
-        })
-        .apply_if(params.keyword.as_ref(), |rows, keyword| {
-            rows.filter(student::Column::Name.contains(keyword))
-        })
-        .apply_if(params.email.as_ref(), |rows, keyword| {
-            rows.filter(student::Column::Email.contains(keyword))
-        })
-        .apply_if(params.sex.as_ref(), |rows, keyword| {
-            rows.filter(student::Column::Sex.eq(keyword))
-        })
-        .apply_if(params.age, |rows, keyword| {
-            rows.filter(student::Column::Age.eq(keyword))
-        })
-        .order_by_asc(student::Column::Id)
-        .paginate(state.db(), params.page.size);
-
-    let total = throw_err!(pagination.num_pages().await);
-    let items = throw_err!(pagination.fetch_page(params.page.index - 1).await);
-
-    AppResult::Ok(Page {
-        param: params.page,
-        total,
-        items,
-    })
 }
@@ -0,0 +1,126 @@
+// DeriveIntoActiveModel 展开后总会带上 `..Default::default()`, InsertParams 的字段齐全时会触发这个 lint
+#![allow(clippy::needless_update)]
+
+use crate::entity::prelude::Term;
+use crate::entity::term::{self, ActiveModel};
+use crate::error::AppError;
+use crate::route::crud::{self, CrudResource};
+use crate::route::openapi::ApiDoc;
+use crate::route::patch;
+use crate::route::request::events::{self, Action, Topic};
+use crate::route::request::import::Importable;
+use crate::route::request::login::{Role, UserIdent};
+use crate::route::routes::Routes;
+use crate::route::version::ApiVersion;
+use schemars::JsonSchema;
+use sea_orm::prelude::Date;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseTransaction, DeriveIntoActiveModel, IntoActiveModel,
+    QueryFilter, QueryTrait, Select,
+};
+use serde::Deserialize;
+use validator::Validate;
+
+pub fn router(version: ApiVersion) -> Routes {
+    crud::router::<Term>(version)
+}
+
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    crud::document::<Term>(doc);
+}
+
+impl CrudResource for Term {
+    const NAME: &'static str = "Term";
+    const TOPIC: Topic = Topic::Term;
+    const FILTERABLE: &'static [term::Column] = &[
+        term::Column::Id,
+        term::Column::Name,
+        term::Column::StartDate,
+        term::Column::EndDate,
+        term::Column::Closed,
+    ];
+    const SORTABLE: &'static [term::Column] = &[
+        term::Column::Id,
+        term::Column::Name,
+        term::Column::StartDate,
+        term::Column::EndDate,
+    ];
+    /// 学期的开关决定了成绩能否直接修改, 只有教务处可以改动
+    const WRITE_ROLES: Option<&'static [Role]> = Some(&[Role::Registrar]);
+
+    type Key = String;
+    type Insert = InsertParams;
+    type Update = InsertParams;
+    type Patch = PatchParams;
+    type Filter = QueryParams;
+
+    fn filter(select: Select<Self>, params: QueryParams) -> Select<Self> {
+        select
+            .apply_if(params.closed, |rows, closed| {
+                rows.filter(term::Column::Closed.eq(closed))
+            })
+            .apply_if(params.date, |rows, date| {
+                rows.filter(term::Column::StartDate.lte(date))
+                    .filter(term::Column::EndDate.gte(date))
+            })
+    }
+}
+
+/// 添加或修改学期所需的参数
+#[derive(Deserialize, Validate, DeriveIntoActiveModel, JsonSchema)]
+#[schemars(rename = "TermInsertParams")]
+pub struct InsertParams {
+    #[validate(length(min = 1, max = 6))]
+    id: String,
+
+    #[validate(length(min = 1, max = 20))]
+    name: String,
+
+    start_date: Date,
+    end_date: Date,
+
+    #[serde(default)]
+    closed: bool,
+}
+
+impl Importable for InsertParams {
+    async fn import(self, txn: &DatabaseTransaction, usr: &UserIdent) -> Result<(), AppError> {
+        let created = self.into_active_model().insert(txn).await?;
+        events::publish(txn, Topic::Term, Action::Insert, usr, &created).await?;
+        Ok(())
+    }
+}
+
+/// 部分更新学期所需的参数, 最常见的用法是 `{"closed": true}`
+#[derive(Deserialize, Validate, JsonSchema)]
+#[schemars(rename = "TermPatchParams")]
+pub struct PatchParams {
+    #[validate(length(min = 1, max = 20))]
+    name: Option<String>,
+
+    start_date: Option<Date>,
+    end_date: Option<Date>,
+    closed: Option<bool>,
+}
+
+impl IntoActiveModel<ActiveModel> for PatchParams {
+    fn into_active_model(self) -> ActiveModel {
+        ActiveModel {
+            name: patch::present(self.name),
+            start_date: patch::present(self.start_date),
+            end_date: patch::present(self.end_date),
+            closed: patch::present(self.closed),
+            ..Default::default()
+        }
+    }
+}
+
+/// 路由到 term 模块下的 query 板块时的所需的参数
+#[derive(Deserialize, Validate, JsonSchema)]
+pub struct QueryParams {
+    closed: Option<bool>,
+
+    /// 查询包含这一天的学期
+    date: Option<Date>,
+}
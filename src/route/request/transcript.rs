@@ -0,0 +1,352 @@
+use crate::app_config;
+use crate::entity::prelude::{Course, Score, Student, Transcript as TranscriptEntity};
+use crate::entity::{department, score, transcript};
+use crate::error::AppError;
+use crate::route::extract::Path;
+use crate::route::openapi::{ApiDoc, Operation};
+use crate::route::request::login::UserIdent;
+use crate::route::result::{AppResult, File};
+use crate::route::routes::Routes;
+use crate::server::ServerState;
+use crate::t;
+use crate::throw_err;
+use axum::extract::State;
+use axum::{Extension, debug_handler};
+use printpdf::{IndirectFontRef, Line, Mm, PdfDocument, PdfLayerReference, Point};
+use schemars::JsonSchema;
+use sea_orm::prelude::{Date, DateTime};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryFilter, QueryOrder,
+};
+use serde::{Deserialize, Serialize};
+
+/// 成绩核验的路由, 不需要登录, 以便任何拿到成绩单的人都能核验
+pub fn router() -> Routes {
+    Routes::new().get("/{code}", verify)
+}
+
+/// 和 [router] 对应的接口文档
+pub fn document(doc: &mut ApiDoc) {
+    doc.get(
+        "/{code}",
+        Operation::new("核验成绩单")
+            .public()
+            .returns::<Verification>(),
+    );
+}
+
+/// 及格线, 只有及格的课程才计入已修学分
+const PASSING_SCORE: i32 = 60;
+
+/// 成绩单的内容, 生成 PDF 时会把它作为快照保存下来
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+pub struct Transcript {
+    student_id: String,
+    student_name: String,
+    sex: Option<String>,
+    department: Option<String>,
+    courses: Vec<CourseLine>,
+    /// 及格课程的学分之和
+    earned_credit: i32,
+    /// 以学分为权重的平均成绩, 没有任何成绩时为空
+    weighted_average: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, PartialEq, Debug)]
+struct CourseLine {
+    course_id: String,
+    course_name: String,
+    credit: Option<i32>,
+    score: Option<i32>,
+    record_date: Option<Date>,
+}
+
+impl Transcript {
+    /// 从数据库中读取学生的信息和所有成绩, 学生不存在时返回 None
+    async fn load(db: &DatabaseConnection, stu_id: &str) -> Result<Option<Self>, DbErr> {
+        let Some((student, department)) = Student::find_by_id(stu_id)
+            .find_also_related(department::Entity)
+            .one(db)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let scores = Score::find()
+            .filter(score::Column::StuId.eq(stu_id))
+            .find_also_related(Course)
+            .order_by_asc(score::Column::RecordDate)
+            .order_by_asc(score::Column::CourseId)
+            .all(db)
+            .await?;
+
+        let courses: Vec<CourseLine> = scores
+            .into_iter()
+            .map(|(score, course)| CourseLine {
+                course_name: course.as_ref().map(|c| c.name.clone()).unwrap_or_default(),
+                credit: course.and_then(|c| c.credit),
+                course_id: score.course_id,
+                score: score.score,
+                record_date: score.record_date,
+            })
+            .collect();
+
+        let earned_credit = courses
+            .iter()
+            .filter(|c| c.score.is_some_and(|s| s >= PASSING_SCORE))
+            .filter_map(|c| c.credit)
+            .sum();
+        let (weighted_sum, credit_sum) = courses
+            .iter()
+            .filter_map(|c| Some((c.score?, c.credit?)))
+            .fold((0i64, 0i64), |(sum, credits), (score, credit)| {
+                (sum + (score * credit) as i64, credits + credit as i64)
+            });
+        let weighted_average = (credit_sum > 0)
+            .then(|| (weighted_sum as f64 / credit_sum as f64 * 100.0).round() / 100.0);
+
+        Ok(Some(Transcript {
+            student_id: student.id,
+            student_name: student.name,
+            sex: student.sex.map(|s| s.trim().to_string()),
+            department: department.and_then(|d| d.name),
+            courses,
+            earned_credit,
+            weighted_average,
+        }))
+    }
+}
+
+/// 生成一份 PDF 成绩单, 每一份成绩单都有一个验证码, 可以通过 `/api/verify/{code}` 核验
+#[debug_handler]
+pub async fn transcript(
+    State(state): State<ServerState>,
+    Extension(usr): Extension<UserIdent>,
+    Path(id): Path<String>,
+) -> AppResult<File> {
+    tracing::debug!("开始处理: 生成成绩单");
+    let Some(font_path) = app_config::get_server().transcript_font() else {
+        return AppResult::Err(AppError::Internal(t!("transcript.no_font")));
+    };
+    let Some(transcript) = throw_err!(Transcript::load(state.db(), &id).await) else {
+        return AppResult::Err(AppError::NotFound(t!("crud.not_found", entity = "Student")));
+    };
+
+    // 16 位十六进制数, 足够长, 无法被猜出来
+    let code = uuid::Uuid::new_v4().simple().to_string()[..16].to_uppercase();
+    let issued_at = chrono::Local::now().naive_local();
+    let content = throw_err!(serde_json::to_value(&transcript).map_err(anyhow::Error::from));
+
+    let font = throw_err!(tokio::fs::read(font_path).await.map_err(|e| {
+        AppError::Internal(t!("transcript.read_font", path = font_path, error = e))
+    }));
+    let render_code = code.clone();
+    let rendered =
+        tokio::task::spawn_blocking(move || render(&transcript, &render_code, issued_at, &font))
+            .await
+            .map_err(anyhow::Error::from);
+    let pdf = throw_err!(throw_err!(rendered));
+
+    throw_err!(
+        transcript::ActiveModel {
+            code: ActiveValue::Set(code.clone()),
+            stu_id: ActiveValue::Set(id.clone()),
+            issued_by: ActiveValue::Set(usr.id.clone()),
+            issued_at: ActiveValue::Set(issued_at),
+            content: ActiveValue::Set(content),
+        }
+        .insert(state.db())
+        .await
+    );
+    tracing::info!("{} 为学生 {id} 生成了一份成绩单, 验证码为 {code}", usr.id);
+
+    AppResult::Ok(File {
+        content_type: "application/pdf",
+        filename: format!("transcript-{id}.pdf"),
+        content: pdf,
+    })
+}
+
+/// A4 纸的尺寸和页边距, 单位为毫米
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const LINE_HEIGHT: f32 = 7.0;
+/// 表格每一列的横坐标
+const COLUMNS: [f32; 5] = [20.0, 45.0, 120.0, 140.0, 160.0];
+
+/// 依次向下书写的 PDF 页面, 写满一页后自动换页
+struct Writer<'a> {
+    doc: &'a printpdf::PdfDocumentReference,
+    layer: PdfLayerReference,
+    font: IndirectFontRef,
+    y: f32,
+}
+
+impl Writer<'_> {
+    fn text(&self, text: &str, size: f32, x: f32) {
+        self.layer
+            .use_text(text, size, Mm(x), Mm(self.y), &self.font);
+    }
+
+    fn rule(&self) {
+        let y = self.y - 2.0;
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(y)), false),
+                (Point::new(Mm(PAGE_WIDTH - MARGIN), Mm(y)), false),
+            ],
+            is_closed: false,
+        });
+    }
+
+    /// 换到下一行, 空间不够时新开一页并重新写表头
+    fn next_line(&mut self) {
+        self.y -= LINE_HEIGHT;
+        if self.y < MARGIN + LINE_HEIGHT * 3.0 {
+            let (page, layer) = self.doc.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "成绩");
+            self.layer = self.doc.get_page(page).get_layer(layer);
+            self.y = PAGE_HEIGHT - MARGIN;
+            self.table_header();
+        }
+    }
+
+    fn table_header(&mut self) {
+        for (title, x) in ["课程号", "课程名称", "学分", "成绩", "记录日期"]
+            .iter()
+            .zip(COLUMNS)
+        {
+            self.text(title, 11.0, x);
+        }
+        self.rule();
+        self.next_line();
+    }
+}
+
+/// 将成绩单渲染为 PDF, `font` 为 TrueType 字体文件的内容, 只有用到的字形会被嵌入
+fn render(
+    transcript: &Transcript,
+    code: &str,
+    issued_at: DateTime,
+    font: &[u8],
+) -> Result<Vec<u8>, AppError> {
+    let pdf_error = |e: printpdf::Error| AppError::Internal(t!("transcript.pdf", error = e));
+    let (doc, page, layer) = PdfDocument::new(
+        format!("{} 的成绩单", transcript.student_name),
+        Mm(PAGE_WIDTH),
+        Mm(PAGE_HEIGHT),
+        "成绩",
+    );
+    let font = doc
+        .add_external_font_with_subsetting(font, true)
+        .map_err(pdf_error)?;
+    let mut writer = Writer {
+        layer: doc.get_page(page).get_layer(layer),
+        doc: &doc,
+        font,
+        y: PAGE_HEIGHT - MARGIN - 5.0,
+    };
+
+    writer.text("学 生 成 绩 单", 20.0, PAGE_WIDTH / 2.0 - 25.0);
+    writer.y -= LINE_HEIGHT * 2.0;
+    writer.text(
+        &format!(
+            "学号: {}    姓名: {}    性别: {}",
+            transcript.student_id,
+            transcript.student_name,
+            transcript.sex.as_deref().unwrap_or("-")
+        ),
+        12.0,
+        MARGIN,
+    );
+    writer.next_line();
+    writer.text(
+        &format!("学院: {}", transcript.department.as_deref().unwrap_or("-")),
+        12.0,
+        MARGIN,
+    );
+    writer.y -= LINE_HEIGHT * 2.0;
+    writer.table_header();
+
+    for course in &transcript.courses {
+        let cells = [
+            course.course_id.trim().to_string(),
+            course.course_name.clone(),
+            course.credit.map_or("-".to_string(), |c| c.to_string()),
+            course.score.map_or("-".to_string(), |s| s.to_string()),
+            course
+                .record_date
+                .map_or("-".to_string(), |d| d.to_string()),
+        ];
+        for (cell, x) in cells.iter().zip(COLUMNS) {
+            writer.text(cell, 10.0, x);
+        }
+        writer.next_line();
+    }
+
+    writer.rule();
+    writer.next_line();
+    writer.text(
+        &format!(
+            "已修学分: {}    加权平均成绩: {}",
+            transcript.earned_credit,
+            transcript
+                .weighted_average
+                .map_or("-".to_string(), |a| format!("{a:.2}"))
+        ),
+        12.0,
+        MARGIN,
+    );
+    writer.y -= LINE_HEIGHT * 2.0;
+    writer.text(
+        &format!("打印时间: {}", issued_at.format("%Y-%m-%d %H:%M:%S")),
+        10.0,
+        MARGIN,
+    );
+    writer.next_line();
+    writer.text(
+        &format!("验证码: {code}    可通过 /api/verify/{code} 核验此成绩单的真伪"),
+        10.0,
+        MARGIN,
+    );
+
+    doc.save_to_bytes().map_err(pdf_error)
+}
+
+/// 成绩单核验的结果
+#[derive(Serialize, JsonSchema)]
+struct Verification {
+    code: String,
+    issued_at: DateTime,
+    /// 打印时的成绩单内容, 和手上的成绩单对比即可发现篡改
+    transcript: Transcript,
+    /// 打印之后成绩是否有变动, 为 false 时说明这份成绩单已经过时了
+    up_to_date: bool,
+}
+
+/// 通过验证码核验一份成绩单
+#[debug_handler]
+async fn verify(
+    State(state): State<ServerState>,
+    Path(code): Path<String>,
+) -> AppResult<Verification> {
+    tracing::debug!("开始处理: 核验成绩单");
+    let code = code.trim().to_uppercase();
+    let issued = throw_err!(TranscriptEntity::find_by_id(&code).one(state.db()).await);
+    let Some(issued) = issued else {
+        tracing::warn!("有人试图核验一份不存在的成绩单, 验证码为 {code}");
+        return AppResult::Err(AppError::NotFound(t!("transcript.forged")));
+    };
+
+    let snapshot: Transcript =
+        throw_err!(serde_json::from_value(issued.content).map_err(anyhow::Error::from));
+    let current = throw_err!(Transcript::load(state.db(), &issued.stu_id).await);
+
+    AppResult::Ok(Verification {
+        up_to_date: current.as_ref() == Some(&snapshot),
+        code: issued.code,
+        issued_at: issued.issued_at,
+        transcript: snapshot,
+    })
+}
@@ -1,4 +1,5 @@
 use crate::error::AppError;
+use axum::http::header;
 use axum::response::IntoResponse;
 use serde::Serialize;
 
@@ -14,7 +15,7 @@ macro_rules! throw_err {
 }
 
 #[derive(Serialize)]
-pub enum AppResult<T: Serialize> {
+pub enum AppResult<T> {
     Err(AppError),
     Ok(T),
 }
@@ -28,6 +29,42 @@ impl<T: Serialize> IntoResponse for AppResult<T> {
     }
 }
 
+/// 不以 JSON 返回的文件, 如 PDF 成绩单, 作为 [AppResult] 的结果时原样返回
+pub struct File {
+    pub content_type: &'static str,
+    /// 响应头 Content-Disposition 中的文件名
+    pub filename: String,
+    pub content: Vec<u8>,
+}
+
+impl IntoResponse for AppResult<File> {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            AppResult::Err(e) => e.into_response(),
+            AppResult::Ok(file) => (
+                [
+                    (header::CONTENT_TYPE, file.content_type.to_string()),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("inline; filename=\"{}\"", file.filename),
+                    ),
+                ],
+                file.content,
+            )
+                .into_response(),
+        }
+    }
+}
+
+impl<T: Serialize, E: Into<AppError>> From<Result<T, E>> for AppResult<T> {
+    fn from(value: Result<T, E>) -> Self {
+        match value {
+            Ok(val) => AppResult::Ok(val),
+            Err(err) => AppResult::Err(err.into()),
+        }
+    }
+}
+
 impl<T: Serialize> From<sea_orm::DbErr> for AppResult<T> {
     fn from(value: sea_orm::DbErr) -> Self {
         AppResult::Err(value.into())
@@ -0,0 +1,126 @@
+use crate::route::version::{self, Deprecated};
+use crate::server::ServerState;
+use axum::extract::Request;
+use axum::handler::Handler;
+use axum::http::Method;
+use axum::response::IntoResponse;
+use axum::routing::{MethodRouter, Route};
+use axum::{Router, middleware, routing};
+use std::convert::Infallible;
+use tower::{Layer, Service};
+
+/// 路由表, 每一个接口都是其中的一条 (方法, 路径, 处理函数), 由它生成 axum 的 [Router]
+///
+/// axum 的 [Router] 没有提供列出路由的接口, 因此由这张表记录下所有的 (方法, 路径),
+/// 接口文档的测试用它和文档对照, 见 [`crate::route::openapi`]
+#[derive(Default)]
+pub struct Routes {
+    routes: Vec<(Method, String, MethodRouter<ServerState>)>,
+}
+
+impl Routes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get<H: Handler<T, ServerState>, T: 'static>(self, path: &str, handler: H) -> Self {
+        self.route(Method::GET, path, routing::get(handler))
+    }
+
+    pub fn post<H: Handler<T, ServerState>, T: 'static>(self, path: &str, handler: H) -> Self {
+        self.route(Method::POST, path, routing::post(handler))
+    }
+
+    pub fn put<H: Handler<T, ServerState>, T: 'static>(self, path: &str, handler: H) -> Self {
+        self.route(Method::PUT, path, routing::put(handler))
+    }
+
+    pub fn patch<H: Handler<T, ServerState>, T: 'static>(self, path: &str, handler: H) -> Self {
+        self.route(Method::PATCH, path, routing::patch(handler))
+    }
+
+    pub fn delete<H: Handler<T, ServerState>, T: 'static>(self, path: &str, handler: H) -> Self {
+        self.route(Method::DELETE, path, routing::delete(handler))
+    }
+
+    fn route(mut self, method: Method, path: &str, route: MethodRouter<ServerState>) -> Self {
+        self.routes.push((method, path.to_string(), route));
+        self
+    }
+
+    /// 将最后添加的一条路由标记为已弃用, 见 [version::deprecated]
+    pub fn deprecated(mut self, deprecated: Deprecated) -> Self {
+        if let Some((_, _, route)) = self.routes.last_mut() {
+            let layer = middleware::from_fn_with_state(deprecated, version::deprecated);
+            *route = std::mem::take(route).layer(layer);
+        }
+        self
+    }
+
+    /// 和 [Router::nest] 对应, `routes` 中的路径都加上 `prefix`, 其中的 "/" 对应的就是前缀本身
+    pub fn nest(mut self, prefix: &str, routes: Routes) -> Self {
+        for (method, path, route) in routes.routes {
+            let path = match path.as_str() {
+                "/" => prefix.to_string(),
+                _ => format!("{prefix}{path}"),
+            };
+            self.routes.push((method, path, route));
+        }
+        self
+    }
+
+    pub fn merge(mut self, routes: Routes) -> Self {
+        self.routes.extend(routes.routes);
+        self
+    }
+
+    /// 和 [Router::route_layer] 对应, 只作用于已经添加了的路由
+    pub fn route_layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request, Error = Infallible> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.routes = self
+            .routes
+            .into_iter()
+            .map(|(method, path, route)| (method, path, route.route_layer(layer.clone())))
+            .collect();
+        self
+    }
+
+    /// 和 [Router::layer] 对应, 只作用于已经添加了的路由
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + Sync + 'static,
+        L::Service: Service<Request> + Clone + Send + Sync + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.routes = self
+            .routes
+            .into_iter()
+            .map(|(method, path, route)| (method, path, route.layer(layer.clone())))
+            .collect();
+        self
+    }
+
+    /// 表中所有的 (方法, 路径)
+    #[cfg(test)]
+    pub fn list(&self) -> impl Iterator<Item = (&Method, &str)> {
+        self.routes
+            .iter()
+            .map(|(method, path, _)| (method, path.as_str()))
+    }
+
+    /// 同一路径的不同方法会被 axum 合并到一起
+    pub fn into_router(self) -> Router<ServerState> {
+        self.routes
+            .into_iter()
+            .fold(Router::new(), |router, (_, path, route)| {
+                router.route(&path, route)
+            })
+    }
+}
@@ -0,0 +1,123 @@
+use crate::comma_list;
+use crate::error::AppError;
+use crate::route::list;
+use crate::t;
+use sea_orm::sea_query::NullOrdering;
+use sea_orm::{EntityTrait, IdenStatic, Iterable, Order, PrimaryKeyToColumn, QueryOrder, Select};
+use serde::{Deserialize, Deserializer};
+
+/// `sort` 参数, 形如 `sort=-score,record_date`, 字段前带 `-` 表示降序
+#[derive(Default, Debug, PartialEq)]
+pub struct SortParam(Vec<SortKey>);
+
+#[derive(Debug, PartialEq)]
+struct SortKey {
+    field: String,
+    desc: bool,
+}
+
+comma_list!(SortParam, "-score,record_date");
+
+impl<'de> Deserialize<'de> for SortKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(SortKey::parse(&s))
+    }
+}
+
+impl SortKey {
+    /// 解析形如 `-score` 的排序字段, 前面带 `-` 表示降序
+    fn parse(key: &str) -> Self {
+        match key.strip_prefix('-') {
+            Some(field) => SortKey {
+                field: field.to_string(),
+                desc: true,
+            },
+            None => SortKey {
+                field: key.trim_start_matches('+').to_string(),
+                desc: false,
+            },
+        }
+    }
+}
+
+impl SortParam {
+    /// 解析形如 `-score,record_date` 的排序字段
+    pub fn parse(s: &str) -> Self {
+        SortParam(list::split(s).map(SortKey::parse).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 按 `sort` 参数为 `select` 排序, 只允许按 `allowed` 中的列排序
+    ///
+    /// 空值无论升序降序都排在最后. 最后总会按主键升序兜底, 保证排序的结果是确定的, 翻页时不会出现重复或遗漏
+    pub fn apply<E: EntityTrait>(
+        &self,
+        mut select: Select<E>,
+        allowed: &[E::Column],
+    ) -> Result<Select<E>, AppError> {
+        for key in &self.0 {
+            let Some(column) = allowed.iter().find(|c| c.as_str() == key.field) else {
+                let fields: Vec<_> = allowed.iter().map(|c| c.as_str()).collect();
+                return Err(AppError::BadRequest(t!(
+                    "sort.unknown_field",
+                    field = key.field,
+                    fields = fields.join(", "),
+                )));
+            };
+            let order = if key.desc { Order::Desc } else { Order::Asc };
+            select = select.order_by_with_nulls(*column, order, NullOrdering::Last);
+        }
+
+        for key in E::PrimaryKey::iter() {
+            let column = key.into_column();
+            if !self.0.iter().any(|k| k.field == column.as_str()) {
+                select = select.order_by_asc(column);
+            }
+        }
+        Ok(select)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::entity::prelude::Score;
+    use crate::entity::score::Column;
+    use sea_orm::{DbBackend, QueryTrait};
+
+    #[test]
+    fn test_sort_param() {
+        let sort: SortParam = serde_json::from_str(r#""-score, record_date,""#).unwrap();
+        let allowed = [Column::Score, Column::RecordDate, Column::StuId];
+
+        let sql = sort
+            .apply(Score::find(), &allowed)
+            .unwrap()
+            .build(DbBackend::Postgres)
+            .to_string();
+        assert!(sql.ends_with(
+            r#"ORDER BY "score"."score" DESC NULLS LAST, "score"."record_date" ASC NULLS LAST, "score"."stu_id" ASC, "score"."course_id" ASC"#
+        ));
+
+        // 已经出现过的主键不会再追加一次
+        let sort: SortParam = serde_json::from_str(r#""-stu_id""#).unwrap();
+        let sql = sort
+            .apply(Score::find(), &allowed)
+            .unwrap()
+            .build(DbBackend::Postgres)
+            .to_string();
+        assert!(
+            sql.ends_with(r#"ORDER BY "score"."stu_id" DESC NULLS LAST, "score"."course_id" ASC"#)
+        );
+
+        let sort: SortParam = serde_json::from_str(r#""course_id""#).unwrap();
+        assert!(matches!(
+            sort.apply(Score::find(), &allowed),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::route::request::login::UserIdent;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+/// 接口的版本, 每个版本挂载在 `/api/v1`, `/api/v2` 这样的路径下, `/api` 是 v1 的别名
+///
+/// 各个版本共用同一套请求处理函数, 不兼容的改动只在新版本中生效, 处理函数可以通过 [current] 区分
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum ApiVersion {
+    /// 最初的接口, 自带的前端使用的就是这个版本
+    #[default]
+    V1,
+    /// 分页结果中去掉了重复的 `total`, 更新和删除记录改为 `PUT /{id}` 和 `DELETE /{id}`
+    V2,
+}
+
+impl ApiVersion {
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+    /// 这个版本在 `/api` 下的路径前缀
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/v1",
+            ApiVersion::V2 => "/v2",
+        }
+    }
+}
+
+tokio::task_local! {
+    static VERSION: ApiVersion;
+}
+
+/// 当前请求所用的接口版本, 不在请求中时 (如 gRPC) 视为 v1
+pub fn current() -> ApiVersion {
+    VERSION.try_with(|version| *version).unwrap_or_default()
+}
+
+/// 以 `version` 处理这个请求, 每个版本的路由上都有一个这样的中间件
+pub async fn scope(State(version): State<ApiVersion>, request: Request, next: Next) -> Response {
+    VERSION.scope(version, next.run(request)).await
+}
+
+/// RFC 9745, 值为弃用时间的 Unix 时间戳, 如 `@1760832000`
+static DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+
+/// RFC 8594, 值为接口停止服务的 HTTP 日期
+static SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// 各个已弃用的接口 (方法和路由) 被调用的次数
+static USAGE: LazyLock<Mutex<HashMap<String, u64>>> = LazyLock::new(Default::default);
+
+/// 已弃用的接口, 通过 [deprecated] 中间件标记在单个路由上:
+///
+/// ```ignore
+/// routing::put(update).layer(middleware::from_fn_with_state(RPC_STYLE, version::deprecated))
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Deprecated {
+    since: NaiveDate,
+    sunset: Option<NaiveDate>,
+}
+
+impl Deprecated {
+    /// 从 `year` 年 `month` 月 `day` 日起弃用
+    pub const fn since(year: i32, month: u32, day: u32) -> Self {
+        Deprecated {
+            since: NaiveDate::from_ymd_opt(year, month, day).expect("弃用日期不合法"),
+            sunset: None,
+        }
+    }
+
+    /// 计划在 `year` 年 `month` 月 `day` 日停止服务
+    pub const fn sunset(self, year: i32, month: u32, day: u32) -> Self {
+        Deprecated {
+            sunset: Some(NaiveDate::from_ymd_opt(year, month, day).expect("停止服务的日期不合法")),
+            ..self
+        }
+    }
+
+    /// 接口文档中的说明
+    pub fn describe(&self) -> String {
+        match self.sunset {
+            Some(sunset) => format!("自 {} 起弃用, 将于 {sunset} 停止服务.", self.since),
+            None => format!("自 {} 起弃用.", self.since),
+        }
+    }
+
+    fn deprecation_header(&self) -> HeaderValue {
+        let timestamp = self
+            .since
+            .and_time(chrono::NaiveTime::MIN)
+            .and_utc()
+            .timestamp();
+        HeaderValue::from_str(&format!("@{timestamp}")).expect("时间戳是合法的响应头")
+    }
+
+    fn sunset_header(&self) -> Option<HeaderValue> {
+        self.sunset.map(|sunset| {
+            let date = sunset
+                .and_time(chrono::NaiveTime::MIN)
+                .and_utc()
+                .format("%a, %d %b %Y %H:%M:%S GMT");
+            HeaderValue::from_str(&date.to_string()).expect("HTTP 日期是合法的响应头")
+        })
+    }
+}
+
+/// 在已弃用接口的响应中加上 `Deprecation` 和 `Sunset` 响应头, 并记录调用次数,
+/// 以便判断什么时候可以真正删除这个接口
+pub async fn deprecated(
+    State(deprecated): State<Deprecated>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or(request.uri().path(), MatchedPath::as_str);
+    let route = format!("{} {path}", request.method());
+    let count = {
+        let mut usage = USAGE.lock().unwrap_or_else(|e| e.into_inner());
+        let count = usage.entry(route.clone()).or_default();
+        *count += 1;
+        *count
+    };
+    match request.extensions().get::<UserIdent>() {
+        Some(usr) => tracing::warn!(
+            "{} 调用了已弃用的接口 {route}, 这个接口已经被调用了 {count} 次",
+            usr.id
+        ),
+        None => tracing::warn!("已弃用的接口 {route} 被调用了, 这个接口已经被调用了 {count} 次"),
+    }
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert(DEPRECATION.clone(), deprecated.deprecation_header());
+    if let Some(sunset) = deprecated.sunset_header() {
+        headers.insert(SUNSET.clone(), sunset);
+    }
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_deprecation_headers() {
+        const DEPRECATED: Deprecated = Deprecated::since(2026, 10, 19).sunset(2027, 10, 19);
+        assert_eq!(DEPRECATED.deprecation_header(), "@1792368000");
+        assert_eq!(
+            DEPRECATED.sunset_header().unwrap(),
+            "Tue, 19 Oct 2027 00:00:00 GMT"
+        );
+        assert!(Deprecated::since(2026, 10, 19).sunset_header().is_none());
+    }
+}
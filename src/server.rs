@@ -1,18 +1,32 @@
-use crate::app_config;
-use axum::Router;
+use crate::error::{REQUEST_ID, error_context};
+use crate::route::idempotency::idempotency;
+use crate::route::request::events::{self, Event};
+use crate::{app_config, i18n};
 use axum::extract::{DefaultBodyLimit, Request};
+use axum::http::{HeaderValue, Uri};
+use axum::{Router, middleware};
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD_NO_PAD;
 use sea_orm::DatabaseConnection;
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::time::Duration;
 use tokio::net::TcpListener;
+use tokio::sync::broadcast;
 use tower_http::cors;
 use tower_http::cors::CorsLayer;
 use tower_http::normalize_path::NormalizePathLayer;
+use tower_http::request_id::{
+    MakeRequestId, PropagateRequestIdLayer, RequestId, SetRequestIdLayer,
+};
 use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::{DefaultOnResponse, TraceLayer};
 
+/// 服务器允许的最大报文大小, 16 MB
+pub const BODY_LIMIT: usize = 1024 * 1024 * 16;
+
+/// 一个请求最多处理多长时间, 超时的请求会被取消
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
 /// 将传进来的 [Router] 和 [ServerState] 绑定，并开始在指定的端口运行服务器
 pub async fn start(router: Router<ServerState>, state: ServerState) -> anyhow::Result<()> {
     let port = app_config::get_server().port();
@@ -36,15 +50,19 @@ fn build_router(router: Router<ServerState>, state: ServerState) -> Router {
     let tracing_layer = TraceLayer::new_for_http()
         .make_span_with(|req: &Request| {
             let method = req.method().to_string();
-            let uri = req.uri().to_string();
-            let id = BASE64_STANDARD_NO_PAD.encode(uuid::Uuid::new_v4()); // 使用 base64 编码的 uuid 作为请求 id
+            let uri = redacted_uri(req.uri());
+            let id = req
+                .headers()
+                .get(&REQUEST_ID)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
             tracing::info_span!("", id, uri, method)
         })
         .on_failure(())
         .on_request(())
         .on_response(DefaultOnResponse::new().level(tracing::Level::INFO));
-    let timeout_layer = TimeoutLayer::new(Duration::from_secs(120));
-    let body_limit_layer = DefaultBodyLimit::max((1024 * 1024 * 16) as usize); // 16 MB 的最大报文大小
+    let timeout_layer = TimeoutLayer::new(REQUEST_TIMEOUT);
+    let body_limit_layer = DefaultBodyLimit::max(BODY_LIMIT);
     let cors_layer = CorsLayer::new()
         .allow_methods(cors::Any)
         .allow_headers(cors::Any)
@@ -52,32 +70,92 @@ fn build_router(router: Router<ServerState>, state: ServerState) -> Router {
         .allow_credentials(false)
         .max_age(Duration::from_secs(3600 * 24));
     let path_normalize_layer = NormalizePathLayer::trim_trailing_slash();
+    let idempotency_layer = middleware::from_fn_with_state(state.clone(), idempotency);
+    let error_context_layer = middleware::from_fn(error_context);
+    let language_layer = middleware::from_fn(i18n::negotiate);
+    let set_request_id_layer = SetRequestIdLayer::new(REQUEST_ID.clone(), Base64Uuid);
+    let propagate_request_id_layer = PropagateRequestIdLayer::new(REQUEST_ID.clone());
 
     router
+        .layer(idempotency_layer)
+        .layer(error_context_layer)
+        .layer(language_layer)
         .layer(path_normalize_layer)
         .layer(timeout_layer)
         .layer(body_limit_layer)
         .layer(tracing_layer)
+        .layer(propagate_request_id_layer)
+        .layer(set_request_id_layer)
         .layer(cors_layer)
         .with_state(state)
 }
 
+/// 日志中记录的 uri, 订阅事件时 token 放在查询参数 `access_token` 中, 不能原样写进日志
+fn redacted_uri(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+    let query = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some(("access_token", _)) => "access_token=***",
+            _ => pair,
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{}?{query}", uri.path())
+}
+
+/// 请求头中没有 `x-request-id` 时, 使用 base64 编码的 uuid 作为请求 id
+#[derive(Clone)]
+struct Base64Uuid;
+
+impl MakeRequestId for Base64Uuid {
+    fn make_request_id<B>(&mut self, _: &Request<B>) -> Option<RequestId> {
+        let id = BASE64_STANDARD_NO_PAD.encode(uuid::Uuid::new_v4());
+        HeaderValue::from_str(&id).ok().map(RequestId::new)
+    }
+}
+
 /// 保存了服务器运行状态，我叫他上下文，不知道对不对，包括
 ///
 /// - 数据库连接池
+/// - 数据变更通知的广播, 见 [events]
 ///
 /// `router` 的分支可以通过这个添加参数获取数据库连接等信息
 #[derive(Clone)]
 pub struct ServerState {
     db: DatabaseConnection,
+    events: broadcast::Sender<Event>,
 }
 
 impl ServerState {
     pub fn new(db: DatabaseConnection) -> Self {
-        Self { db }
+        let (events, _) = broadcast::channel(events::CAPACITY);
+        Self { db, events }
     }
 
     pub fn db(&self) -> &DatabaseConnection {
         &self.db
     }
+
+    pub fn events(&self) -> &broadcast::Sender<Event> {
+        &self.events
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_redacted_uri() {
+        let uri = Uri::from_static("/api/v1/events?entity=score&access_token=abc.def.ghi&x=1");
+        assert_eq!(
+            redacted_uri(&uri),
+            "/api/v1/events?entity=score&access_token=***&x=1"
+        );
+        let uri = Uri::from_static("/api/v1/score?page=2");
+        assert_eq!(redacted_uri(&uri), "/api/v1/score?page=2");
+    }
 }